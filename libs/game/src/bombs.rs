@@ -0,0 +1,114 @@
+// Colour bombs: half-hexes that, when their hex matches, set off every other half-hex with
+// the same colours as them. Those go off in rings spreading out from the bomb, rather than all
+// at once, (see `Burst`.)
+//
+// A match clears two half-hexes of the bomb's colours, and the bomb clears the rest, so
+// afterwards there are none of that colour left. That means if every colour was on the grid an
+// even number of times before, it still is. That includes half-hexes that are part way through
+// a swap, which aren't on the grid until they land, (see `set_off_in_flight`.)
+use crate::game::{get_colours, is_bomb, is_multi_colour, Grid, GridCell, HalfHexSpec};
+use crate::matching::ClearedHex;
+
+// A half-hex that a bomb has set off. The cell is `Animating` until it goes, which is `stage`
+// steps after the first ring, counting out from the bomb.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Burst {
+    pub index: usize,
+    pub spec: HalfHexSpec,
+    pub stage: usize,
+}
+
+// The index and spec of the bomb in each hex of `cleared` that has one.
+pub(crate) fn bombs_in(cleared: &[ClearedHex]) -> impl Iterator<Item = (usize, HalfHexSpec)> + '_ {
+    cleared.iter().filter_map(|hex| {
+        if is_bomb(hex.left) {
+            Some((hex.index, hex.left))
+        } else if is_bomb(hex.right) {
+            Some((hex.index, hex.right))
+        } else {
+            None
+        }
+    })
+}
+
+// Sets off any bombs in `cleared`, and returns the half-hexes they set off.
+pub(crate) fn set_off_bombs(grid: &mut Grid, cleared: &[ClearedHex]) -> Vec<Burst> {
+    let mut bursts = Vec::new();
+    for (bomb_index, bomb) in bombs_in(cleared) {
+        let colours = get_colours(bomb);
+
+        let stages = grid.steps_from(bomb_index);
+        // On a grid in pieces, some cells can't be reached from the bomb. Those still go, so
+        // none of the colour is left, just after the furthest ring that can be.
+        let unreachable = stages
+            .iter()
+            .filter(|&&stage| stage != usize::MAX)
+            .max()
+            .map_or(0, |&stage| stage + 1);
+        for (index, &stage) in stages.iter().enumerate() {
+            if let GridCell::Present(spec) = grid[index] {
+                if !is_multi_colour(spec) && get_colours(spec) == colours {
+                    grid[index] = GridCell::Animating;
+                    let stage = stage.min(unreachable);
+                    bursts.push(Burst { index, spec, stage });
+                }
+            }
+        }
+    }
+
+    bursts
+}
+
+// Sets off the half-hexes in flight with the same colours as any of `bombs`, by taking away
+// their specs, so they land as nothing.
+pub(crate) fn set_off_in_flight<'a, I>(bombs: &[HalfHexSpec], in_flight: I)
+where
+    I: Iterator<Item = &'a mut Option<HalfHexSpec>>,
+{
+    if bombs.is_empty() {
+        return;
+    }
+
+    for spec in in_flight {
+        if let Some(s) = *spec {
+            if !is_multi_colour(s) && bombs.iter().any(|&b| get_colours(b) == get_colours(s)) {
+                *spec = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{BOMB, GRID_HEIGHT, GRID_WIDTH};
+    use crate::matching::resolve_matches;
+
+    #[test]
+    fn a_matched_bomb_sets_off_its_colour_further_away_later_and_leaves_the_rest() {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        let bomb_hex = grid.xy_to_i(20, 30);
+        grid[bomb_hex] = GridCell::Present(0x12 | BOMB);
+        grid[bomb_hex + 1] = GridCell::Present(0x12);
+
+        let near = grid.xy_to_i(24, 30);
+        let far = grid.xy_to_i(37, 2);
+        let other_colour = grid.xy_to_i(25, 30);
+        let multi_colour = grid.xy_to_i(3, 40);
+        grid[near] = GridCell::Present(0x12);
+        grid[far] = GridCell::Present(0x12);
+        grid[other_colour] = GridCell::Present(0x22);
+        grid[multi_colour] = GridCell::Present(0b0100_0101);
+
+        let cleared = resolve_matches(&mut grid);
+        let bursts = set_off_bombs(&mut grid, &cleared);
+
+        assert_eq!(
+            bursts.iter().map(|b| b.index).collect::<Vec<_>>(),
+            vec![far, near]
+        );
+        assert!(bursts[0].stage > bursts[1].stage);
+        assert!(grid[near].is_animating() && grid[far].is_animating());
+        assert!(grid[other_colour].is_present() && grid[multi_colour].is_present());
+    }
+}
@@ -0,0 +1,103 @@
+// Cursors that cover more than one half-hex, so whole groups get swapped at once.
+//
+// A shape is the cursor's own half-hex, (its anchor,) plus a list of other cells, each given as
+// the moves it takes to get there from the anchor. Since the moves are made with
+// `get_hex_index`, the shape follows the `MOVEMENT` table, and so comes out the same on every
+// row type. Nothing says the moves have to stay inside the shape, so a shape can be in pieces.
+use crate::game::{Dir, Grid};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CursorShape {
+    paths: Vec<Vec<Dir>>,
+}
+
+impl Default for CursorShape {
+    fn default() -> Self {
+        CursorShape::single()
+    }
+}
+
+impl CursorShape {
+    /// `paths` are the cells other than the anchor.
+    pub fn new(paths: Vec<Vec<Dir>>) -> Self {
+        CursorShape { paths }
+    }
+
+    pub fn single() -> Self {
+        CursorShape::new(Vec::new())
+    }
+
+    /// The shapes that `Select` and `A` cycle through in the game.
+    pub fn presets() -> Vec<CursorShape> {
+        use Dir::*;
+        vec![
+            CursorShape::single(),
+            // Two half-hexes side by side.
+            CursorShape::new(vec![vec![Right]]),
+            // A column of three.
+            CursorShape::new(vec![vec![Down], vec![Down, Down]]),
+            // Two half-hexes with a gap between them.
+            CursorShape::new(vec![vec![Right, Right]]),
+        ]
+    }
+
+    pub fn paths(&self) -> &[Vec<Dir>] {
+        &self.paths
+    }
+
+    fn len(&self) -> usize {
+        self.paths.len() + 1
+    }
+
+    /// The cells the shape covers when its anchor is at `anchor`, anchor first, then in the
+    /// order of the paths. `None` if any of them would be off the grid, or if two of them
+    /// would be the same cell.
+    pub fn cells(&self, grid: &Grid, anchor: usize) -> Option<Vec<usize>> {
+        let mut cells = Vec::with_capacity(self.len());
+        cells.push(anchor);
+        for path in self.paths.iter() {
+            let mut index = anchor;
+            for &dir in path.iter() {
+                index = grid.get_hex_index(index, dir)?;
+            }
+            if cells.contains(&index) {
+                return None;
+            }
+            cells.push(index);
+        }
+
+        Some(cells)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::ROW_TYPES;
+
+    #[test]
+    fn shapes_move_through_every_row_type_and_do_not_fit_off_the_grid() {
+        let grid = Grid::new(12, 9);
+        let column = &CursorShape::presets()[2];
+
+        for y in 0..ROW_TYPES {
+            let anchor = grid.xy_to_i(4, y);
+            let cells = column.cells(&grid, anchor).expect("fits");
+            assert_eq!(cells.len(), 3);
+            assert_eq!(
+                cells[1..].to_vec(),
+                vec![
+                    grid.get_hex_index(anchor, Dir::Down).unwrap(),
+                    grid.get_hex_index(cells[1], Dir::Down).unwrap()
+                ]
+            );
+        }
+
+        let bottom = grid.xy_to_i(4, 8);
+        assert_eq!(column.cells(&grid, bottom), None);
+
+        // Left then right again gets back to the anchor.
+        let back = CursorShape::new(vec![vec![Dir::Left, Dir::Right]]);
+        assert_eq!(back.cells(&grid, grid.xy_to_i(5, 4)), None);
+    }
+}
@@ -0,0 +1,146 @@
+// Noticing when the player is stuck, and shuffling the grid up so they aren't any more.
+//
+// This only looks one swap ahead, (see `hint.rs`,) so a grid where the only way forward is to
+// move something into an empty spot and let gravity make the match counts as stuck too. The
+// player gets offered a reshuffle rather than given one, so that's fine.
+use crate::cursor_shape::CursorShape;
+use crate::game::{get_colours, Grid, GridCell, HalfHexSpec, KIND_MASK};
+use crate::hint::find_hint;
+use crate::rules::{MechanicKind, RuleSet};
+use crate::solver::has_even_colour_counts;
+use rand::Rng;
+use std::collections::BTreeMap;
+
+// A reshuffle that still leaves the player stuck gets tried again, up to this many times.
+const RESHUFFLE_ATTEMPTS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stuck {
+    // No swap makes a match straight away.
+    NoMatchingSwap,
+    // Some colour is on the grid an odd number of times, so it can never all be cleared.
+    OddColourCount,
+}
+
+/// Why the player can't get any further with `grid`, if they can't. `grid` should have
+/// nothing in flight.
+pub fn find_stuck(grid: &Grid, rules: &RuleSet) -> Option<Stuck> {
+    if !grid.iter().any(|c| c.is_present()) || !rules.is_enabled(MechanicKind::Matching) {
+        return None;
+    }
+
+    // With `Refill` on, the goal is to fill the grid rather than clear it. The player can always
+    // go back to a single cursor, so that's the one to look for a swap with.
+    if !rules.is_enabled(MechanicKind::Refill) && !has_even_colour_counts(grid) {
+        Some(Stuck::OddColourCount)
+    } else if find_hint(grid, &CursorShape::single(), 0).is_none() {
+        Some(Stuck::NoMatchingSwap)
+    } else {
+        None
+    }
+}
+
+/// Moves the half-hexes around among the cells that have one in them, so every colour is on
+/// the grid as many times as before. If a colour was on there an odd number of times, one of
+/// them gets the colours of another odd one, or if there's only one left over, it goes.
+pub fn reshuffle<R: Rng>(grid: &mut Grid, rng: &mut R) {
+    let mut cells: Vec<(usize, HalfHexSpec)> = grid
+        .iter()
+        .enumerate()
+        .filter_map(|(i, cell)| match *cell {
+            GridCell::Present(spec) => Some((i, spec)),
+            _ => None,
+        })
+        .collect();
+
+    // `has_even_colour_counts` only says no when there are no multi-colour half-hexes.
+    if !has_even_colour_counts(grid) {
+        // Where in `cells` one of each colour that is on the grid an odd number of times is.
+        let mut odd: BTreeMap<(u32, u32), usize> = BTreeMap::new();
+        for (position, &(_, spec)) in cells.iter().enumerate() {
+            let colours = get_colours(spec);
+            if odd.remove(&colours).is_none() {
+                odd.insert(colours, position);
+            }
+        }
+
+        let odd: Vec<usize> = odd.into_values().collect();
+        for pair in odd.chunks(2) {
+            if let [first, second] = *pair {
+                cells[second].1 = cells[first].1 & !KIND_MASK;
+            }
+        }
+        if odd.len() % 2 == 1 {
+            let (index, _) = cells.remove(odd[odd.len() - 1]);
+            grid[index] = GridCell::Absent;
+        }
+    }
+
+    let (indexes, mut specs): (Vec<usize>, Vec<HalfHexSpec>) = cells.into_iter().unzip();
+    for _ in 0..RESHUFFLE_ATTEMPTS {
+        rng.shuffle(&mut specs);
+        for (&i, &spec) in indexes.iter().zip(specs.iter()) {
+            grid[i] = GridCell::Present(spec);
+        }
+        if find_hint(grid, &CursorShape::single(), 0).is_some() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GRID_HEIGHT, GRID_WIDTH};
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+
+    fn colour_counts(grid: &Grid) -> BTreeMap<(u32, u32), usize> {
+        let mut counts = BTreeMap::new();
+        for cell in grid.iter() {
+            if let GridCell::Present(spec) = *cell {
+                *counts.entry(get_colours(spec)).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn stuck_grids_get_noticed_and_reshuffles_leave_even_colour_counts() {
+        let rules = RuleSet::default();
+        let refill = RuleSet::default().with(MechanicKind::Refill, true);
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        let start = grid.xy_to_i(18, 30);
+        for (i, &spec) in [0x00, 0x11, 0x22, 0x33].iter().enumerate() {
+            grid[start + i] = GridCell::Present(spec);
+        }
+        assert_eq!(find_stuck(&grid, &rules), Some(Stuck::OddColourCount));
+        assert_eq!(find_stuck(&grid, &refill), Some(Stuck::NoMatchingSwap));
+
+        let mut rng = XorShiftRng::from_seed([9; 16]);
+        reshuffle(&mut grid, &mut rng);
+        assert_eq!(
+            colour_counts(&grid).values().collect::<Vec<_>>(),
+            vec![&2, &2]
+        );
+        assert_eq!(find_stuck(&grid, &rules), None);
+
+        // Counts that are already even stay the same.
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        for (i, &spec) in [0x00, 0x11, 0x22, 0x00, 0x11, 0x22].iter().enumerate() {
+            grid[start + i] = GridCell::Present(spec);
+        }
+        let counts = colour_counts(&grid);
+        reshuffle(&mut grid, &mut rng);
+        assert_eq!(colour_counts(&grid), counts);
+
+        // With a colour left over, it goes.
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        for (i, &spec) in [0x00, 0x11, 0x22].iter().enumerate() {
+            grid[start + i] = GridCell::Present(spec);
+        }
+        reshuffle(&mut grid, &mut rng);
+        assert_eq!(colour_counts(&grid).values().collect::<Vec<_>>(), vec![&2]);
+        assert!(has_even_colour_counts(&grid));
+    }
+}
@@ -1,41 +1,51 @@
+use crate::bombs::{set_off_in_flight, Burst};
+use crate::cursor_shape::CursorShape;
+use crate::dead_board::{find_stuck, reshuffle, Stuck};
+use crate::hint::find_hint;
+use crate::history::History;
+use crate::limits::{Ending, Limit, FRAMES_PER_SECOND};
+use crate::refill::{fill_percent, Spawn};
+use crate::rules::{MechanicKind, Outcome, RuleSet};
+use crate::scoring::Score;
 use features::{invariants_checked, log, GLOBAL_ERROR_LOGGER, GLOBAL_LOGGER};
 use platform_types::{Button, Input, Speaker, State, StateParams, SFX};
 use rand::prng::XorShiftRng;
 use rand::{Rng, SeedableRng};
-use rendering::{Framebuffer, BLUE, GREY, PALETTE, PURPLE, WHITE, YELLOW};
+use rendering::{
+    get_sprite_xy, Framebuffer, BLACK, BLUE, GREY, PALETTE, PURPLE, SCREEN_HEIGHT, SCREEN_WIDTH,
+    WALL_SPRITE, WHITE, WHITE_INDEX, YELLOW,
+};
+use std::collections::VecDeque;
 
-const GRID_WIDTH: u8 = 40;
-const GRID_HEIGHT: u8 = 60;
-const GRID_LENGTH: usize = GRID_WIDTH as usize * GRID_HEIGHT as usize;
+// The size of generated grids. Other grids can be any size that fits on the screen.
+pub const GRID_WIDTH: u8 = 40;
+pub const GRID_HEIGHT: u8 = 60;
 
-#[derive(Copy, Clone, PartialEq, Eq)]
-enum GridCell<T> {
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum GridCell<T> {
     Absent,
     Present(T),
     Animating,
+    // Never moves, and can't be selected. Things fall up against it like anything else.
+    Wall,
 }
 
 #[allow(dead_code)]
 impl<T> GridCell<T> {
     pub fn is_present(&self) -> bool {
-        match self {
-            GridCell::Present(_) => true,
-            _ => false,
-        }
+        matches!(self, GridCell::Present(_))
     }
 
     pub fn is_absent(&self) -> bool {
-        match self {
-            GridCell::Absent => true,
-            _ => false,
-        }
+        matches!(self, GridCell::Absent)
     }
 
     pub fn is_animating(&self) -> bool {
-        match self {
-            GridCell::Animating => true,
-            _ => false,
-        }
+        matches!(self, GridCell::Animating)
+    }
+
+    pub fn is_wall(&self) -> bool {
+        matches!(self, GridCell::Wall)
     }
 
     pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> GridCell<U> {
@@ -43,6 +53,7 @@ impl<T> GridCell<T> {
             GridCell::Present(x) => GridCell::Present(f(x)),
             GridCell::Absent => GridCell::Absent,
             GridCell::Animating => GridCell::Animating,
+            GridCell::Wall => GridCell::Wall,
         }
     }
 
@@ -51,6 +62,7 @@ impl<T> GridCell<T> {
             GridCell::Present(x) => f(x),
             GridCell::Absent => GridCell::Absent,
             GridCell::Animating => GridCell::Animating,
+            GridCell::Wall => GridCell::Wall,
         }
     }
 }
@@ -64,18 +76,51 @@ macro_rules! on_left {
     };
 }
 
-type HalfHexSpec = u8;
+pub type HalfHexSpec = u8;
+
+// Bits 2 and 3 of a `HalfHexSpec` say what kind of half-hex it is. For plain half-hexes, bits
+// 0 and 1 are the inside colour and bits 4 and 5 are the outline colour.
+pub const KIND_MASK: HalfHexSpec = 0b0000_1100;
+pub const PLAIN: HalfHexSpec = 0b0000_0000;
+// These have a set of inside colours in bits 4 to 7, one bit per colour, and the outline colour
+// in bits 0 and 1.
+pub const MULTI_COLOUR: HalfHexSpec = 0b0000_0100;
+// These are laid out like plain half-hexes, and match like them too, (see `bombs.rs`.)
+pub const BOMB: HalfHexSpec = 0b0000_1000;
+// So are these, (see `scramble.rs`.)
+pub const SCRAMBLE: HalfHexSpec = 0b0000_1100;
+
+pub fn is_multi_colour(spec: HalfHexSpec) -> bool {
+    spec & KIND_MASK == MULTI_COLOUR
+}
 
-fn get_colours(mut spec: HalfHexSpec) -> (u32, u32) {
-    spec &= 0b0011_0011; //cut out BLACK (0b111)
+pub fn is_bomb(spec: HalfHexSpec) -> bool {
+    spec & KIND_MASK == BOMB
+}
+
+pub fn is_scramble(spec: HalfHexSpec) -> bool {
+    spec & KIND_MASK == SCRAMBLE
+}
+
+// The inside colours, as a set of bits, and the outline colour, as palette indexes.
+pub(crate) fn colour_set(spec: HalfHexSpec) -> (u8, u8) {
+    if is_multi_colour(spec) {
+        (spec >> 4, spec & 0b11)
+    } else {
+        (1 << (spec & 0b11), (spec >> 4) & 0b11)
+    }
+}
+
+pub(crate) fn get_colours(spec: HalfHexSpec) -> (u32, u32) {
+    let (insides, outline) = colour_set(spec);
     (
-        PALETTE[(spec & 0b111) as usize],
-        PALETTE[(spec >> 4) as usize],
+        PALETTE[insides.trailing_zeros() as usize & 0b11],
+        PALETTE[outline as usize],
     )
 }
 
 #[derive(Clone, Copy)]
-enum Cursor {
+pub(crate) enum Cursor {
     Unselected(usize),
     Selected(usize, usize),
 }
@@ -103,23 +148,33 @@ impl From<Cursor> for usize {
 }
 
 impl Cursor {
-    fn iter(&self) -> impl Iterator<Item = usize> {
+    // Every cell covered by the cursor, and by the group it has selected, if any.
+    fn cells(&self, grid: &Grid, shape: &CursorShape) -> Vec<usize> {
         use Cursor::*;
-        match *self {
-            Unselected(c) => vec![c].into_iter(),
-            Selected(c1, c2) => vec![c1, c2].into_iter(),
-        }
+        let anchors = match *self {
+            Unselected(c) => vec![c],
+            Selected(c1, c2) => vec![c1, c2],
+        };
+        anchors
+            .into_iter()
+            .flat_map(|a| shape.cells(grid, a).unwrap_or_else(|| vec![a]))
+            .collect()
     }
 }
 
-struct Animation {
-    x: u8,
-    y: u8,
-    target_x: u8,
-    target_y: u8,
-    x_rate: u8,
-    y_rate: u8,
-    spec: Option<HalfHexSpec>,
+pub(crate) struct Animation {
+    pub(crate) x: u8,
+    pub(crate) y: u8,
+    pub(crate) target_x: u8,
+    pub(crate) target_y: u8,
+    pub(crate) x_rate: u8,
+    pub(crate) y_rate: u8,
+    pub(crate) spec: Option<HalfHexSpec>,
+    // Both animations in a swap share this, so we can tell when the whole swap has landed.
+    // Half-hexes that `Refill` spawned are not part of a swap.
+    pub(crate) swap: Option<usize>,
+    // Whether the other half of the swap cleared anything when it landed.
+    pub(crate) swap_cleared: bool,
 }
 
 use std::cmp::{max, min};
@@ -127,27 +182,18 @@ use std::cmp::{max, min};
 const DELAY_FACTOR: u8 = 16;
 
 impl Animation {
-    pub fn new<H: Into<Option<HalfHexSpec>>>(i: usize, target_i: usize, spec: H) -> Self {
+    pub fn new<H: Into<Option<HalfHexSpec>>>(
+        grid: &Grid,
+        i: usize,
+        target_i: usize,
+        spec: H,
+        swap: Option<usize>,
+    ) -> Self {
         let spec = spec.into();
-        let (x, y) = i_to_xy(i);
-        let (target_x, target_y) = i_to_xy(target_i);
+        let (x, y) = grid.i_to_xy(i);
+        let (target_x, target_y) = grid.i_to_xy(target_i);
 
-        let (x_diff, y_diff) = (
-            if target_x == x {
-                0
-            } else if x > target_x {
-                x - target_x
-            } else {
-                target_x - x
-            },
-            if target_y == y {
-                0
-            } else if y > target_y {
-                y - target_y
-            } else {
-                target_y - y
-            },
-        );
+        let (x_diff, y_diff) = (x.abs_diff(target_x), y.abs_diff(target_y));
 
         Animation {
             x,
@@ -157,6 +203,8 @@ impl Animation {
             target_x,
             target_y,
             spec,
+            swap,
+            swap_cleared: false,
         }
     }
 
@@ -169,12 +217,12 @@ impl Animation {
 
         self.x = match d_x {
             x if x > 0 => self.x.saturating_add(x as u8),
-            x if x < 0 => self.x.saturating_sub(x.abs() as u8),
+            x if x < 0 => self.x.saturating_sub(x.unsigned_abs()),
             _ => self.x,
         };
         self.y = match d_y {
             y if y > 0 => self.y.saturating_add(y as u8),
-            y if y < 0 => self.y.saturating_sub(y.abs() as u8),
+            y if y < 0 => self.y.saturating_sub(y.unsigned_abs()),
             _ => self.y,
         };
     }
@@ -209,7 +257,7 @@ fn advance_animations(state: &mut GameState, speaker: &mut Speaker) {
         animation.approach_target();
 
         if animation.is_complete() {
-            let index = xy_to_i(animation.x, animation.y);
+            let index = state.grid.xy_to_i(animation.x, animation.y);
 
             if state.grid[index].is_present() {
                 //hope it is moved soon?
@@ -222,82 +270,482 @@ fn advance_animations(state: &mut GameState, speaker: &mut Speaker) {
                 GridCell::Absent
             };
 
-            let other_index = if on_left!(animation.x) {
-                index + 1
-            } else {
-                index - 1
-            };
-            if state.grid[other_index].map(get_colours) == state.grid[index].map(get_colours) {
-                state.grid[other_index] = GridCell::Absent;
-                state.grid[index] = GridCell::Absent;
-            }
+            let swap = animation.swap;
+            let outcome = state
+                .rules
+                .landed(&mut state.grid, &mut state.rng, &[index]);
+            show_outcome(state, speaker, &outcome);
 
+            let swap_cleared =
+                state.animations[animation_index].swap_cleared || !outcome.cleared.is_empty();
             state.animations.swap_remove(animation_index);
-            apply_gravity_once(&mut state.grid, speaker);
+            match swap {
+                Some(swap) => {
+                    state.score.swap_cleared(outcome.pairs_cleared());
+                    match state.animations.iter_mut().find(|a| a.swap == Some(swap)) {
+                        Some(other_half) => other_half.swap_cleared = swap_cleared,
+                        None => state.score.swap_landed(swap_cleared),
+                    }
+                }
+                // Nobody swapped these into place, so they score like anything else that fell.
+                None => state.score.gravity_cleared(outcome.pairs_cleared()),
+            }
+
             speaker.request_sfx(SFX::MovePiece);
         }
     }
 }
 
+// Starts off whatever needs animating, or sounds, after the rules have run.
+fn show_outcome(state: &mut GameState, speaker: &mut Speaker, outcome: &Outcome) {
+    for _ in 0..outcome.moved {
+        speaker.request_sfx(SFX::Wud);
+    }
+    animate_spawns(state, &outcome.spawned);
+    light_fuses(state, &outcome.bursts);
+    set_off_in_flight(
+        &outcome.bombs,
+        state.animations.iter_mut().map(|a| &mut a.spec),
+    );
+    if !outcome.scrambled.is_empty() {
+        speaker.request_sfx(SFX::Scramble);
+        state.scrambled = outcome.scrambled.clone();
+        state.scramble_frames_left = SCRAMBLE_CUE_FRAMES;
+    }
+}
+
+fn animate_spawns(state: &mut GameState, spawns: &[Spawn]) {
+    for spawn in spawns.iter() {
+        state.animations.push(Animation::new(
+            &state.grid,
+            spawn.from,
+            spawn.to,
+            spawn.spec,
+            None,
+        ));
+    }
+}
+
+// A half-hex that a bomb set off. It stays on the grid, as an `Animating` cell, until
+// `frames_left` runs out, and flashes for the last `FLASH_FRAMES` of those.
+pub(crate) struct Fuse {
+    pub(crate) index: usize,
+    pub(crate) spec: HalfHexSpec,
+    pub(crate) frames_left: usize,
+}
+
+const FLASH_FRAMES: usize = 8;
+
+// How long the half-hexes a scramble moved are outlined for.
+const SCRAMBLE_CUE_FRAMES: u8 = 32;
+
+fn light_fuses(state: &mut GameState, bursts: &[Burst]) {
+    for burst in bursts.iter() {
+        state.fuses.push(Fuse {
+            index: burst.index,
+            spec: burst.spec,
+            // Each ring out from the bomb goes a frame after the one before it.
+            frames_left: burst.stage.saturating_add(FLASH_FRAMES),
+        });
+    }
+}
+
+fn advance_fuses(state: &mut GameState) {
+    let grid = &mut state.grid;
+    state.fuses.retain_mut(|fuse| {
+        fuse.frames_left = fuse.frames_left.saturating_sub(1);
+        if fuse.frames_left == 0 {
+            grid[fuse.index] = GridCell::Absent;
+        }
+        fuse.frames_left > 0
+    });
+}
+
 pub struct GameState {
-    grid: Grid,
-    cursor: Cursor,
-    frame_counter: usize,
-    animations: Vec<Animation>,
-    rng: XorShiftRng,
+    pub(crate) grid: Grid,
+    pub(crate) cursor: Cursor,
+    pub(crate) cursor_shape: CursorShape,
+    pub(crate) frame_counter: usize,
+    // Frames played this round, which stops counting once the round ends.
+    pub(crate) frames_played: usize,
+    pub(crate) ending: Option<Ending>,
+    pub(crate) stuck: Option<Stuck>,
+    // What the round started with, so it can be tried again.
+    pub(crate) start_grid: Grid,
+    pub(crate) start_cursor: usize,
+    pub(crate) animations: Vec<Animation>,
+    pub(crate) fuses: Vec<Fuse>,
+    // The half-hexes that the last scramble moved, for as long as they are outlined.
+    pub(crate) scrambled: Vec<usize>,
+    pub(crate) scramble_frames_left: u8,
+    pub(crate) history: History<(Grid, Score)>,
+    pub(crate) score: Score,
+    pub(crate) swap_count: usize,
+    pub(crate) rules: RuleSet,
+    pub(crate) rng: XorShiftRng,
 }
 
-type Grid = [GridCell<HalfHexSpec>; GRID_LENGTH];
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Grid {
+    // in half-hexes, so this is always even.
+    width: u8,
+    height: u8,
+    cells: Vec<GridCell<HalfHexSpec>>,
+}
+
+impl std::ops::Deref for Grid {
+    type Target = [GridCell<HalfHexSpec>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.cells
+    }
+}
 
-fn new_grid<R: Rng>(rng: &mut R) -> Grid {
-    use std::collections::HashMap;
-    let mut counts: HashMap<HalfHexSpec, bool> = HashMap::with_capacity(256);
+impl std::ops::DerefMut for Grid {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.cells
+    }
+}
 
-    let mut grid: Grid = [GridCell::Absent; GRID_LENGTH];
+impl Grid {
+    /// Makes an empty grid. `width` is measured in half-hexes, so it must be even.
+    pub fn new(width: u8, height: u8) -> Grid {
+        assert!(
+            width > 0 && width & 1 == 0 && height > 0,
+            "a {}x{} grid does not make sense",
+            width,
+            height
+        );
+
+        Grid {
+            width,
+            height,
+            cells: vec![GridCell::Absent; width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u8 {
+        self.width
+    }
+
+    pub fn height(&self) -> u8 {
+        self.height
+    }
+
+    pub fn i_to_xy(&self, i: usize) -> (u8, u8) {
+        (
+            (i % self.width as usize) as u8,
+            (i / self.width as usize) as u8,
+        )
+    }
+
+    pub fn xy_to_i(&self, x: u8, y: u8) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+
+    pub(crate) fn partner_index(&self, index: usize) -> usize {
+        let (x, _) = self.i_to_xy(index);
+        if on_left!(x) {
+            index + 1
+        } else {
+            index - 1
+        }
+    }
+
+    pub(crate) fn get_hex_index(&self, index: usize, dir: Dir) -> Option<usize> {
+        let (x, y) = self.i_to_xy(index);
+        let movement = ((y % ROW_TYPES) << 3) | (on_left!(x, bit) << 2) | dir as u8;
+        let (rows, columns) = MOVEMENT[movement as usize];
+
+        // Going by the row and the column separately, rather than the offset into `cells`,
+        // means a move off one side never comes back on the other, however narrow the grid.
+        let new_x = x as isize + columns as isize;
+        let new_y = y as isize + rows as isize;
+        if (0..self.width as isize).contains(&new_x) && (0..self.height as isize).contains(&new_y) {
+            Some(self.xy_to_i(new_x as u8, new_y as u8))
+        } else {
+            None
+        }
+    }
+
+    // How many moves in any `Dir` it takes to get from `start` to each cell, going through
+    // cells whatever is in them. Cells that can't be reached get `usize::MAX`.
+    pub(crate) fn steps_from(&self, start: usize) -> Vec<usize> {
+        let mut steps = vec![usize::MAX; self.len()];
+        let mut queue = VecDeque::new();
+        steps[start] = 0;
+        queue.push_back(start);
+        while let Some(index) = queue.pop_front() {
+            for &dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter() {
+                if let Some(i) = self.get_hex_index(index, dir) {
+                    if steps[i] == usize::MAX {
+                        steps[i] = steps[index] + 1;
+                        queue.push_back(i);
+                    }
+                }
+            }
+        }
+
+        steps
+    }
+}
+
+pub(crate) fn new_grid<R: Rng>(rng: &mut R) -> Grid {
     let mut c: HalfHexSpec = rng.gen();
-    for i in 0..GRID_LENGTH {
-        let (x, y) = i_to_xy(i);
-        if x <= 1 || x >= GRID_WIDTH - 2 || y <= 1 || y >= GRID_HEIGHT - 2 {
+    fill_grid(
+        rng,
+        (GRID_WIDTH, GRID_HEIGHT),
+        |x, y| !(x <= 1 || x >= GRID_WIDTH - 2 || y <= 1 || y >= GRID_HEIGHT - 2),
+        || {
+            let spec = c & !KIND_MASK;
+            c = c.wrapping_add(1);
+            spec
+        },
+    )
+}
+
+// Puts a half-hex from `next_spec` everywhere `in_shape` says to, then removes half-hexes
+// until there is an even number of each colour left.
+pub(crate) fn fill_grid<R, S, N>(
+    rng: &mut R,
+    (width, height): (u8, u8),
+    in_shape: S,
+    mut next_spec: N,
+) -> Grid
+where
+    R: Rng,
+    S: Fn(u8, u8) -> bool,
+    N: FnMut() -> HalfHexSpec,
+{
+    // This is iterated over below, and a `HashMap` would go through it in a different order each
+    // run, which would make the grid depend on more than the seed.
+    use std::collections::BTreeMap;
+    let mut counts: BTreeMap<(u32, u32), bool> = BTreeMap::new();
+
+    let mut grid = Grid::new(width, height);
+    let length = grid.len();
+    for i in 0..length {
+        let (x, y) = grid.i_to_xy(i);
+        if !in_shape(x, y) {
             continue;
         }
 
+        let c = next_spec();
         grid[i] = GridCell::Present(c);
-        let e = counts.entry(c).or_default();
+        let e = counts.entry(get_colours(c)).or_default();
         *e = !*e;
-        c = c.wrapping_add(1);
     }
 
-    // This isn't O(256 * GRID_LENGTH) in practice given the colurs are distributed such that
+    // This isn't O(256 * length) in practice given the colurs are distributed such that
     // we hit a cell of any given colour quickly, as is currently the case.
-    for (c, odd) in counts {
+    for (colours, odd) in counts {
         if odd {
-            let mut index = rng.gen_range(0, GRID_LENGTH);
-            for _ in 0..GRID_LENGTH {
-                if grid[index] == GridCell::Present(c) {
+            let mut index = rng.gen_range(0, length);
+            for _ in 0..length {
+                if grid[index].map(get_colours) == GridCell::Present(colours) {
                     grid[index] = GridCell::Absent;
                     break;
                 }
 
-                index = (index + 1) % GRID_LENGTH;
+                index = (index + 1) % length;
             }
         }
     }
     grid
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridError {
+    TooBigForScreen(u8, u8),
+    CursorOutOfBounds(usize),
+    AnimatingCell(usize),
+    OddColourCount(HalfHexSpec),
+}
+
+impl std::fmt::Display for GridError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use GridError::*;
+        match *self {
+            TooBigForScreen(width, height) => {
+                write!(f, "a {}x{} grid does not fit on the screen", width, height)
+            }
+            CursorOutOfBounds(i) => write!(f, "cursor index {} is outside the grid", i),
+            AnimatingCell(i) => write!(f, "cell {} is animating, which has no meaning here", i),
+            OddColourCount(spec) => write!(
+                f,
+                "there is an odd number of half-hexes coloured like {:#010b}",
+                spec
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
 impl GameState {
-    pub fn new(seed: [u8; 16]) -> GameState {
+    pub fn new(seed: [u8; 16], rules: RuleSet) -> GameState {
         let mut rng = XorShiftRng::from_seed(seed);
         let grid: Grid = new_grid(&mut rng);
 
-        GameState {
+        GameState::from_parts(grid, GRID_WIDTH as usize + 1, rng, rules)
+    }
+
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    pub fn score(&self) -> &Score {
+        &self.score
+    }
+
+    pub fn rules(&self) -> &RuleSet {
+        &self.rules
+    }
+
+    /// Returns `false`, and keeps the current shape, if `shape` does not fit where the cursor
+    /// is.
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) -> bool {
+        let anchor = self.cursor.into();
+        if shape.cells(&self.grid, anchor).is_none() {
+            return false;
+        }
+
+        self.cursor_shape = shape;
+        self.cursor = Cursor::Unselected(anchor);
+        true
+    }
+
+    /// How the round ended, if it has.
+    pub fn ending(&self) -> Option<Ending> {
+        self.ending
+    }
+
+    /// Why the player can't get any further, if they can't, and the round isn't over.
+    pub fn stuck(&self) -> Option<Stuck> {
+        self.stuck
+    }
+
+    /// Whether the grid is clear, or with `Refill` on, whether it is full enough.
+    pub fn is_won(&self) -> bool {
+        if self.rules.is_enabled(MechanicKind::Refill) {
+            fill_percent(&self.grid) >= self.rules.fill_goal_percent
+        } else {
+            self.grid.iter().all(|c| c.is_absent() || c.is_wall())
+        }
+    }
+
+    /// Use a hand-authored grid instead of a generated one. The grid must not contain any
+    /// `Animating` cells, and every colour must appear an even number of times, or the grid
+    /// could never be cleared.
+    pub fn from_grid(
+        seed: [u8; 16],
+        grid: Grid,
+        cursor: usize,
+        rules: RuleSet,
+    ) -> Result<GameState, GridError> {
+        use std::collections::HashMap;
+
+        if !fits_on_screen(grid.width(), grid.height()) {
+            return Err(GridError::TooBigForScreen(grid.width(), grid.height()));
+        }
+
+        if cursor >= grid.len() {
+            return Err(GridError::CursorOutOfBounds(cursor));
+        }
+
+        let mut odd_colours: HashMap<(u32, u32), HalfHexSpec> = HashMap::new();
+        let mut any_multi_colour = false;
+        for (i, cell) in grid.iter().enumerate() {
+            match *cell {
+                GridCell::Present(spec) if is_multi_colour(spec) => any_multi_colour = true,
+                GridCell::Present(spec) => {
+                    let colours = get_colours(spec);
+                    if odd_colours.remove(&colours).is_none() {
+                        odd_colours.insert(colours, spec);
+                    }
+                }
+                GridCell::Animating => return Err(GridError::AnimatingCell(i)),
+                GridCell::Absent | GridCell::Wall => {}
+            }
+        }
+
+        // A multi-colour half-hex can pair up with more than one colour, so then we can't tell.
+        if let (Some(&spec), false) = (odd_colours.values().min(), any_multi_colour) {
+            return Err(GridError::OddColourCount(spec));
+        }
+
+        Ok(GameState::from_parts(
             grid,
-            cursor: Cursor::Unselected(GRID_WIDTH as usize + 1),
+            cursor,
+            XorShiftRng::from_seed(seed),
+            rules,
+        ))
+    }
+
+    fn from_parts(grid: Grid, cursor: usize, rng: XorShiftRng, rules: RuleSet) -> GameState {
+        GameState {
+            animations: Vec::with_capacity(grid.width() as usize),
+            fuses: Vec::new(),
+            scrambled: Vec::new(),
+            scramble_frames_left: 0,
+            grid: grid.clone(),
+            cursor: Cursor::Unselected(cursor),
+            cursor_shape: CursorShape::single(),
             frame_counter: 0,
-            animations: Vec::with_capacity(GRID_WIDTH as usize),
+            frames_played: 0,
+            ending: None,
+            stuck: None,
+            start_grid: grid,
+            start_cursor: cursor,
+            history: History::new(),
+            score: Score::new(),
+            swap_count: 0,
+            rules,
             rng,
         }
     }
+
+    // A new grid after a win, otherwise the same one again.
+    fn restart(&mut self) {
+        if self.ending == Some(Ending::Won) {
+            self.start_grid = new_grid(&mut self.rng);
+            self.start_cursor = GRID_WIDTH as usize + 1;
+        }
+        self.grid = self.start_grid.clone();
+        self.cursor = Cursor::Unselected(self.start_cursor);
+        if self
+            .cursor_shape
+            .cells(&self.grid, self.start_cursor)
+            .is_none()
+        {
+            self.cursor_shape = CursorShape::single();
+        }
+        self.animations.clear();
+        self.fuses.clear();
+        self.scrambled.clear();
+        self.scramble_frames_left = 0;
+        self.history.clear();
+        self.score = Score::new();
+        self.swap_count = 0;
+        self.frames_played = 0;
+        self.ending = None;
+        self.stuck = None;
+    }
+
+    // The grid as it will be once every animation in flight has landed, and every lit fuse
+    // has gone off. Any matches this makes get cleared on the next step.
+    fn landed_grid(&self) -> Grid {
+        let mut grid = self.grid.clone();
+        for animation in self.animations.iter() {
+            let index = grid.xy_to_i(animation.target_x, animation.target_y);
+            grid[index] = animation.spec.map_or(GridCell::Absent, GridCell::Present);
+        }
+        for fuse in self.fuses.iter() {
+            grid[fuse.index] = GridCell::Absent;
+        }
+
+        grid
+    }
 }
 
 pub struct EntireState {
@@ -309,16 +757,20 @@ pub struct EntireState {
 
 impl EntireState {
     pub fn new((seed, logger, error_logger): StateParams) -> Self {
-        let framebuffer = Framebuffer::new();
-
         unsafe {
             GLOBAL_LOGGER = logger;
             GLOBAL_ERROR_LOGGER = error_logger;
         }
 
+        EntireState::from_seed(seed)
+    }
+
+    /// Like `new`, but leaves the loggers as they are, so it can be used while another state
+    /// is running, to play a replay say.
+    pub fn from_seed(seed: [u8; 16]) -> Self {
         EntireState {
-            game_state: GameState::new(seed),
-            framebuffer,
+            game_state: GameState::new(seed, RuleSet::default()),
+            framebuffer: Framebuffer::new(),
             input: Input::new(),
             speaker: Speaker::new(),
         }
@@ -358,27 +810,60 @@ impl State for EntireState {
     fn get_frame_buffer(&self) -> &[u32] {
         &self.framebuffer.buffer
     }
+
+    fn save(&self) -> String {
+        self.game_state.save()
+    }
+
+    // Whatever buttons were held go with the old state.
+    fn load(&mut self, saved: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.game_state = GameState::load(saved)?;
+        self.input = Input::new();
+        Ok(())
+    }
 }
 
 const HEX_WIDTH: u8 = 4;
 const HEX_HEIGHT: u8 = 8;
 const HALF_HEX_HEIGHT: u8 = HEX_HEIGHT / 2;
-const EDGE_OFFSET: u8 = 6;
 
-const ROW_TYPES: u8 = 3;
+pub(crate) const ROW_TYPES: u8 = 3;
+
+fn grid_pixel_dimensions(width: u8, height: u8) -> (usize, usize) {
+    let widest_row_offset = min(height - 1, ROW_TYPES - 1) as usize * HEX_WIDTH as usize;
+    (
+        width as usize * 6 - 4 + widest_row_offset,
+        (height as usize - 1) * HALF_HEX_HEIGHT as usize + HEX_HEIGHT as usize,
+    )
+}
+
+// Anything bigger would overflow the pixel maths in `p_xy`.
+pub(crate) fn fits_on_screen(width: u8, height: u8) -> bool {
+    let (pixel_width, pixel_height) = grid_pixel_dimensions(width, height);
+    // The cursor is drawn one pixel outside the grid.
+    pixel_width + 2 <= SCREEN_WIDTH && pixel_height + 2 <= SCREEN_HEIGHT
+}
+
+fn p_xy(grid: &Grid, x: u8, y: u8) -> (u8, u8) {
+    // This keeps the grid in the center of the screen.
+    let (pixel_width, pixel_height) = grid_pixel_dimensions(grid.width(), grid.height());
+    let edge_x = (SCREEN_WIDTH.saturating_sub(pixel_width) / 2) as u8;
+    let edge_y = (SCREEN_HEIGHT.saturating_sub(pixel_height) / 2) as u8;
 
-fn p_xy(x: u8, y: u8) -> (u8, u8) {
     let x_offset = (y % ROW_TYPES) * HEX_WIDTH;
     if on_left!(x) {
-        (
-            x * 6 + x_offset + EDGE_OFFSET,
-            y * HALF_HEX_HEIGHT + EDGE_OFFSET,
-        )
+        (x * 6 + x_offset + edge_x, y * HALF_HEX_HEIGHT + edge_y)
     } else {
-        (
-            x * 6 + x_offset - 2 + EDGE_OFFSET,
-            y * HALF_HEX_HEIGHT + EDGE_OFFSET,
-        )
+        (x * 6 + x_offset - 2 + edge_x, y * HALF_HEX_HEIGHT + edge_y)
+    }
+}
+
+// Blinks, so it can't be mistaken for the cursor.
+fn hint_shader(frame_counter: usize) -> fn(usize, usize, usize, usize) -> u32 {
+    if frame_counter & 0b1_0000 == 0 {
+        |_, _, _, _| WHITE
+    } else {
+        |_, _, _, _| BLACK
     }
 }
 
@@ -406,116 +891,121 @@ fn marching_ants(frame_counter: usize) -> fn(usize, usize, usize, usize) -> u32
     }
 }
 
-fn is_index_next_to(x: u8, _y: u8, index: usize) -> bool {
-    if index < GRID_LENGTH {
-        let width = GRID_WIDTH as usize;
-        let new_x = index % width;
-        let looped = (x == 0 && new_x == width - 1) || (x as usize == width - 1 && new_x == 0);
-        if !looped {
-            return true;
-        }
-    }
-    false
-}
-
-//see `design/gridMovement.md` for the derivation of this table.
-static MOVEMENT: [i8; 24] = {
-    const W: i8 = GRID_WIDTH as i8;
-
-    [
-        -(W + 1),
-        2 * W - 1,
-        W - 1,
-        1,
-        -(2 * W + 1),
-        W - 1,
-        -1,
-        -(W + 1),
-        -(W - 1),
-        2 * W + 1,
-        W - 1,
-        1,
-        -(2 * W + 1),
-        W - 1,
-        -1,
-        -(W - 1),
-        -(W - 1),
-        2 * W + 1,
-        W + 1,
-        1,
-        -(2 * W - 1),
-        W + 1,
-        -1,
-        -(W - 1),
-    ]
-};
-
-#[derive(Clone, Copy)]
-enum Dir {
+//see `design/gridMovement.md` for the derivation of this table. Each entry is `(a, b)` where
+//the offset is `a * W + b` for a grid `W` half-hexes wide.
+static MOVEMENT: [(i8, i8); 24] = [
+    (-1, -1),
+    (2, -1),
+    (1, -1),
+    (0, 1),
+    (-2, -1),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 1),
+    (2, 1),
+    (1, -1),
+    (0, 1),
+    (-2, -1),
+    (1, -1),
+    (0, -1),
+    (-1, 1),
+    (-1, 1),
+    (2, 1),
+    (1, 1),
+    (0, 1),
+    (-2, 1),
+    (1, 1),
+    (0, -1),
+    (-1, 1),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dir {
     Up,
     Down,
     Left,
     Right,
 }
 
-fn get_movement_offset(x: u8, y: u8, dir: Dir) -> i8 {
-    let index = ((y % ROW_TYPES) << 3) | (on_left!(x, bit) << 2) | dir as u8;
-
-    MOVEMENT[index as usize]
-}
+fn draw_hexagon(framebuffer: &mut Framebuffer, grid: &Grid, x: u8, y: u8, spec: HalfHexSpec) {
+    let (inside, outline) = get_colours(spec);
 
-fn get_hex_index(index: usize, dir: Dir) -> Option<usize> {
-    let (x, y) = i_to_xy(index);
-    let new_index = index.wrapping_add(get_movement_offset(x, y, dir) as usize);
+    let (p_x, p_y) = p_xy(grid, x, y);
+    if is_multi_colour(spec) {
+        // The inside is split into a stripe for each colour, top to bottom.
+        let (insides, outline_index) = colour_set(spec);
+        let colours: Vec<u32> = (0..4)
+            .filter(|i| insides & (1 << i) != 0)
+            .map(|i| PALETTE[i])
+            .collect();
+        let outline = PALETTE[outline_index as usize];
+        let hex_xs = if on_left!(x) { 0..4 } else { 4..8 };
+        for hex_y in 0..HEX_HEIGHT {
+            // The inside covers rows 1 to 6.
+            let stripe = (hex_y.saturating_sub(1) as usize * colours.len()) / 6;
+            let inside = colours
+                .get(stripe.min(colours.len().saturating_sub(1)))
+                .cloned()
+                .unwrap_or(outline);
+            for hex_x in hex_xs.clone() {
+                framebuffer.hexagon_set_pixel(p_x, p_y, hex_x, hex_y, inside, outline);
+            }
+        }
+    } else if on_left!(x) {
+        framebuffer.hexagon_left(p_x, p_y, inside, outline);
+    } else {
+        framebuffer.hexagon_right(p_x, p_y, inside, outline);
+    }
 
-    if is_index_next_to(x, y, new_index) {
-        Some(new_index)
+    // A spot in the middle, dark for the fuse of a bomb, or light for a scramble.
+    let spot = if is_bomb(spec) {
+        Some(BLACK)
+    } else if is_scramble(spec) {
+        Some(WHITE)
     } else {
         None
+    };
+    if let Some(spot) = spot {
+        let hex_xs = if on_left!(x) { 2..4 } else { 4..6 };
+        for hex_y in 3..5 {
+            for hex_x in hex_xs.clone() {
+                framebuffer.hexagon_set_pixel(p_x, p_y, hex_x, hex_y, spot, spot);
+            }
+        }
     }
 }
 
-fn i_to_xy(i: usize) -> (u8, u8) {
-    (
-        (i % GRID_WIDTH as usize) as u8,
-        (i / GRID_WIDTH as usize) as u8,
-    )
-}
-
-fn xy_to_i(x: u8, y: u8) -> usize {
-    y as usize * GRID_WIDTH as usize + x as usize
+fn draw_wall(framebuffer: &mut Framebuffer, grid: &Grid, x: u8, y: u8) {
+    let (p_x, p_y) = p_xy(grid, x, y);
+    let (sprite_x, sprite_y) = get_sprite_xy(WALL_SPRITE);
+    // The sprite is a whole hex, so this takes the half we want.
+    let sprite_x = if on_left!(x) { sprite_x } else { sprite_x + 4 };
+    framebuffer.sspr(sprite_x, sprite_y, 4, HEX_HEIGHT, p_x, p_y);
 }
 
-fn draw_hexagon(framebuffer: &mut Framebuffer, x: u8, y: u8, spec: HalfHexSpec) {
-    let (inside, outline) = get_colours(spec);
-
-    let (p_x, p_y) = p_xy(x, y);
-    if on_left!(x) {
-        framebuffer.hexagon_left(p_x, p_y, inside, outline);
-    } else {
-        framebuffer.hexagon_right(p_x, p_y, inside, outline);
-    }
-}
-
-fn apply_gravity_once(grid: &mut Grid, speaker: &mut Speaker) {
+// Moves every half-hex that can fall one step towards `(middle_x, middle_y)`, and returns how
+// many moved.
+pub(crate) fn fall_once(grid: &mut Grid, (middle_x, middle_y): (i16, i16)) -> usize {
+    let mut moved = 0;
     for index in 0..grid.len() {
         if let GridCell::Present(half_hex) = grid[index] {
-            let (x, y) = i_to_xy(index);
+            let (x, y) = grid.i_to_xy(index);
 
-            // these 8 hal-hexes are the ones in the very middle that would cause animation loops
+            // these 8 hal-hexes are the ones right around the middle that would cause animation loops
             // otherwise. It appears this restriction would be unnecessary if there was an odd
             // number of rows/columns.
-            if ((x == GRID_WIDTH / 2 - 1 || x == GRID_WIDTH / 2 - 2) && y == GRID_HEIGHT / 2 - 1)
-                || ((x == GRID_WIDTH / 2 || x == GRID_WIDTH / 2 + 1) && y == GRID_HEIGHT / 2 - 2)
-                || ((x == GRID_WIDTH / 2 - 1 || x == GRID_WIDTH / 2 - 2)
-                    && y == GRID_HEIGHT / 2 + 1)
-                || ((x == GRID_WIDTH / 2 || x == GRID_WIDTH / 2 + 1) && y == GRID_HEIGHT / 2)
+            let (x_i16, y_i16) = (x as i16, y as i16);
+            if ((x_i16 == middle_x - 1 || x_i16 == middle_x - 2) && y_i16 == middle_y - 1)
+                || ((x_i16 == middle_x || x_i16 == middle_x + 1) && y_i16 == middle_y - 2)
+                || ((x_i16 == middle_x - 1 || x_i16 == middle_x - 2) && y_i16 == middle_y + 1)
+                || ((x_i16 == middle_x || x_i16 == middle_x + 1) && y_i16 == middle_y)
             {
                 continue;
             }
 
-            let should_fall_right = x < GRID_WIDTH / 2;
-            let should_fall_down = y < GRID_HEIGHT / 2;
+            let should_fall_right = x_i16 < middle_x;
+            let should_fall_down = y_i16 < middle_y;
 
             let (forward_x, backward_x) = if should_fall_right {
                 (Dir::Right, Dir::Left)
@@ -536,22 +1026,22 @@ fn apply_gravity_once(grid: &mut Grid, speaker: &mut Speaker) {
                         ($forward_x_index, $forward_xy_index, $forward_y_index)
                     {
                         if [forward_x_index, forward_xy_index, forward_y_index]
-                            .into_iter()
+                            .iter()
                             .map(|i| &grid[*i])
                             .all(|h| h.is_absent())
                         {
                             grid[forward_xy_index] = GridCell::Present(half_hex);
                             grid[index] = GridCell::Absent;
-                            speaker.request_sfx(SFX::Wud);
+                            moved += 1;
                             continue;
                         }
                     }
                 };
             }
 
-            let forward_x_index = get_hex_index(index, forward_x);
-            let forward_xy_index = forward_x_index.and_then(|i| get_hex_index(i, forward_y));
-            let forward_y_index = get_hex_index(index, forward_y);
+            let forward_x_index = grid.get_hex_index(index, forward_x);
+            let forward_xy_index = forward_x_index.and_then(|i| grid.get_hex_index(i, forward_y));
+            let forward_y_index = grid.get_hex_index(index, forward_y);
 
             //  →
             // ↓↘
@@ -559,14 +1049,14 @@ fn apply_gravity_once(grid: &mut Grid, speaker: &mut Speaker) {
             move_if_possible!(target forward_xy_index, forward_x_index, forward_y_index);
 
             let backward_x_forward_y_index =
-                forward_y_index.and_then(|i| get_hex_index(i, backward_x));
+                forward_y_index.and_then(|i| grid.get_hex_index(i, backward_x));
 
             // ↙↓↘
 
             move_if_possible!(target forward_xy_index, backward_x_forward_y_index, forward_y_index);
 
             let forward_x_backward_y_index =
-                forward_x_index.and_then(|i| get_hex_index(i, backward_y));
+                forward_x_index.and_then(|i| grid.get_hex_index(i, backward_y));
 
             // ↗
             // →
@@ -575,6 +1065,8 @@ fn apply_gravity_once(grid: &mut Grid, speaker: &mut Speaker) {
             move_if_possible!(target forward_xy_index, forward_x_backward_y_index, forward_x_index);
         }
     }
+
+    moved
 }
 
 #[inline]
@@ -588,9 +1080,30 @@ pub fn update_and_render(
     //UPDATE
     //
     advance_animations(state, speaker);
-    apply_gravity_once(&mut state.grid, speaker);
-
-    let is_empty = state.grid.iter().all(|c| c.is_absent());
+    advance_fuses(state);
+    state.scramble_frames_left = state.scramble_frames_left.saturating_sub(1);
+
+    let outcome = state.rules.step(&mut state.grid, &mut state.rng);
+    state.score.gravity_cleared(outcome.pairs_cleared());
+    show_outcome(state, speaker, &outcome);
+
+    let settled =
+        state.animations.is_empty() && state.fuses.is_empty() && !outcome.changed_anything();
+    if state.ending.is_none() {
+        state.frames_played += 1;
+        state.ending = state.rules.limit.ending(
+            state.is_won(),
+            state.swap_count,
+            state.frames_played,
+            settled,
+        );
+    }
+    // Only worth looking at once everything has played out.
+    state.stuck = if settled && state.ending.is_none() {
+        find_stuck(&state.grid, &state.rules)
+    } else {
+        None
+    };
 
     match input.gamepad {
         Button::B => framebuffer.clear_to(BLUE),
@@ -603,42 +1116,128 @@ pub fn update_and_render(
         _ => {}
     }
 
-    if input.pressed_this_frame(Button::Start) && is_empty {
-        state.grid = new_grid(&mut state.rng);
+    if input.pressed_this_frame(Button::Start) {
+        if state.ending.is_some() {
+            state.restart();
+        } else if state.stuck.is_some() {
+            speaker.request_sfx(SFX::Scramble);
+            state.history.record((state.grid.clone(), state.score));
+            reshuffle(&mut state.grid, &mut state.rng);
+            state.stuck = None;
+            state.cursor = Cursor::Unselected(state.cursor.into());
+        }
+    }
+    // Once the round is over, only `Start` does anything.
+    let playing = state.ending.is_none();
+
+    // `Select` with `A` or `B` does something else, so it only shows a hint on its own.
+    let hint = if playing
+        && input.gamepad.contains(Button::Select)
+        && !input.gamepad.intersects(Button::A | Button::B)
+        && state.rules.is_enabled(MechanicKind::Matching)
+    {
+        find_hint(
+            &state.landed_grid(),
+            &state.cursor_shape,
+            state.cursor.into(),
+        )
+    } else {
+        None
+    };
+
+    if playing && input.pressed_this_frame(Button::B) {
+        // Anything still animating is skipped ahead to where it was going, so it is part of
+        // what gets redone, or undone.
+        let current = (state.landed_grid(), state.score);
+        let restored = if input.gamepad.contains(Button::Select) {
+            state.history.redo(current)
+        } else {
+            state.history.undo(current)
+        };
+
+        if let Some((grid, score)) = restored {
+            state.animations.clear();
+            state.fuses.clear();
+            state.scramble_frames_left = 0;
+            state.grid = grid;
+            state.score = score;
+            state.cursor = Cursor::Unselected(state.cursor.into());
+        }
     }
-    if input.pressed_this_frame(Button::A) {
+    if playing && input.pressed_this_frame(Button::A) && input.gamepad.contains(Button::Select) {
+        // On to the next shape that fits where the cursor is.
+        let presets = CursorShape::presets();
+        let current = presets
+            .iter()
+            .position(|shape| *shape == state.cursor_shape)
+            .unwrap_or(0);
+        for offset in 1..=presets.len() {
+            if state.set_cursor_shape(presets[(current + offset) % presets.len()].clone()) {
+                break;
+            }
+        }
+    } else if playing && input.pressed_this_frame(Button::A) {
         match state.cursor {
             Cursor::Unselected(c) => {
-                if !state.grid[c].is_animating() {
-                    state.cursor = Cursor::Selected(c, c);
+                if let Some(cells) = state.cursor_shape.cells(&state.grid, c) {
+                    if cells
+                        .iter()
+                        .all(|&i| !state.grid[i].is_animating() && !state.grid[i].is_wall())
+                    {
+                        state.cursor = Cursor::Selected(c, c);
+                    }
                 }
             }
+            // Picking the same place again puts the selection back down, rather than swapping
+            // it with itself, which would count as a move without changing anything.
+            Cursor::Selected(c1, c2) if c1 == c2 => {
+                state.cursor = Cursor::Unselected(c2);
+            }
             Cursor::Selected(c1, c2) => {
-                macro_rules! animate {
-                    ($h1: expr, $h2: expr) => {
-                        speaker.request_sfx(SFX::MovePiece);
-                        state.grid[c1] = GridCell::Animating;
-                        state.grid[c2] = GridCell::Animating;
-                        state.animations.push(Animation::new(c1, c2, $h1));
-                        state.animations.push(Animation::new(c2, c1, $h2));
-                        state.cursor = Cursor::Unselected(c2);
+                if let (Some(from), Some(to)) = (
+                    state.cursor_shape.cells(&state.grid, c1),
+                    state.cursor_shape.cells(&state.grid, c2),
+                ) {
+                    // If the groups overlap, some half-hexes would be in two swaps at once.
+                    let overlaps = from.iter().any(|i| to.contains(i));
+                    let spec_of = |i: usize| match state.grid[i] {
+                        GridCell::Present(h) => Some(Some(h)),
+                        GridCell::Absent => Some(None),
+                        GridCell::Animating | GridCell::Wall => None,
                     };
-                }
+                    let specs: Option<Vec<(Option<HalfHexSpec>, Option<HalfHexSpec>)>> = from
+                        .iter()
+                        .zip(to.iter())
+                        .map(|(&i1, &i2)| Some((spec_of(i1)?, spec_of(i2)?)))
+                        .collect();
 
-                match (state.grid[c1], state.grid[c2]) {
-                    (GridCell::Present(h1), GridCell::Present(h2)) => {
-                        animate!(h1, h2);
-                    }
-                    (GridCell::Present(h1), GridCell::Absent) => {
-                        animate!(h1, None);
-                    }
-                    (GridCell::Absent, GridCell::Present(h2)) => {
-                        animate!(None, h2);
-                    }
-                    (GridCell::Absent, GridCell::Absent) => {
-                        animate!(None, None);
+                    let allowed = state.rules.limit.allows_swap(state.swap_count);
+
+                    if let (false, true, Some(specs)) = (overlaps, allowed, specs) {
+                        speaker.request_sfx(SFX::MovePiece);
+                        state.history.record((state.landed_grid(), state.score));
+                        state.swap_count += 1;
+                        for ((&i1, &i2), &(h1, h2)) in from.iter().zip(to.iter()).zip(specs.iter())
+                        {
+                            state.grid[i1] = GridCell::Animating;
+                            state.grid[i2] = GridCell::Animating;
+                            state.animations.push(Animation::new(
+                                &state.grid,
+                                i1,
+                                i2,
+                                h1,
+                                Some(state.swap_count),
+                            ));
+                            state.animations.push(Animation::new(
+                                &state.grid,
+                                i2,
+                                i1,
+                                h2,
+                                Some(state.swap_count),
+                            ));
+                        }
+                        state.cursor = Cursor::Unselected(c2);
                     }
-                    _ => {}
                 }
             }
         };
@@ -648,29 +1247,31 @@ pub fn update_and_render(
         ($dir: expr) => {
             let cursor_num: usize = state.cursor.into();
 
-            let (x, y) = i_to_xy(cursor_num);
-
-            let offset: i8 = get_movement_offset(x, y, $dir);
-
-            let new_cursor = state.cursor.wrapping_add(offset as usize);
-            let new_cursor_num: usize = new_cursor.into();
-
-            if is_index_next_to(x, y, new_cursor_num) {
-                state.cursor = new_cursor;
+            if let Some(new_cursor_num) = state.grid.get_hex_index(cursor_num, $dir) {
+                // The whole shape has to stay on the grid.
+                if state
+                    .cursor_shape
+                    .cells(&state.grid, new_cursor_num)
+                    .is_some()
+                {
+                    state.cursor = state
+                        .cursor
+                        .wrapping_add(new_cursor_num.wrapping_sub(cursor_num));
+                }
             }
         };
     }
 
-    if input.pressed_this_frame(Button::Up) {
+    if playing && input.pressed_this_frame(Button::Up) {
         move_cursor!(Dir::Up);
     }
-    if input.pressed_this_frame(Button::Down) {
+    if playing && input.pressed_this_frame(Button::Down) {
         move_cursor!(Dir::Down);
     }
-    if input.pressed_this_frame(Button::Left) {
+    if playing && input.pressed_this_frame(Button::Left) {
         move_cursor!(Dir::Left);
     }
-    if input.pressed_this_frame(Button::Right) {
+    if playing && input.pressed_this_frame(Button::Right) {
         move_cursor!(Dir::Right);
     }
 
@@ -680,20 +1281,96 @@ pub fn update_and_render(
 
     framebuffer.clear_to(GREY);
 
-    if is_empty {
-        framebuffer.print_line(b"you did it! press enter to do it again!", 40, 40, 7);
+    framebuffer.print_line(b"score", 2, 0, WHITE_INDEX);
+    framebuffer.print_single_line_number(state.score.points as usize, 26, 0, WHITE_INDEX);
+    if state.score.combo > 1 {
+        framebuffer.print_line(b"combo", 200, 0, WHITE_INDEX);
+        framebuffer.print_single_line_number(state.score.combo as usize, 224, 0, WHITE_INDEX);
+    }
+    if state.rules.is_enabled(MechanicKind::Refill) {
+        let fill = fill_percent(&state.grid);
+        framebuffer.print_line(b"fill", 100, 0, WHITE_INDEX);
+        framebuffer.print_single_line_number(fill as usize, 120, 0, WHITE_INDEX);
+        framebuffer.print_line(b"goal", 140, 0, WHITE_INDEX);
+        framebuffer.print_single_line_number(
+            state.rules.fill_goal_percent as usize,
+            160,
+            0,
+            WHITE_INDEX,
+        );
+    }
+
+    match state.rules.limit {
+        Limit::Moves(moves) => {
+            framebuffer.print_line(b"moves", 60, 0, WHITE_INDEX);
+            let left = moves.saturating_sub(state.swap_count);
+            framebuffer.print_single_line_number(left, 84, 0, WHITE_INDEX);
+        }
+        Limit::Frames(frames) => {
+            framebuffer.print_line(b"time", 60, 0, WHITE_INDEX);
+            // Rounded up, so it only says zero once the time is up.
+            let left = frames.saturating_sub(state.frames_played);
+            let seconds = left.div_ceil(FRAMES_PER_SECOND);
+            framebuffer.print_single_line_number(seconds, 80, 0, WHITE_INDEX);
+        }
+        Limit::None => {}
+    }
+
+    if let Some(ending) = state.ending {
+        let message: &[u8] = match ending {
+            Ending::Won => b"you did it! press enter to do it again!",
+            Ending::OutOfMoves => b"out of moves! press enter to try again!",
+            Ending::OutOfTime => b"out of time! press enter to try again!",
+        };
+        framebuffer.print_line(message, 40, 40, 7);
+        framebuffer.print_line(b"score", 40, 52, WHITE_INDEX);
+        framebuffer.print_single_line_number(state.score.points as usize, 64, 52, WHITE_INDEX);
+        framebuffer.print_line(b"swaps", 40, 62, WHITE_INDEX);
+        framebuffer.print_single_line_number(state.swap_count, 64, 62, WHITE_INDEX);
     } else {
-        for y in 0..GRID_HEIGHT {
-            for x in 0..GRID_WIDTH {
-                if let GridCell::Present(spec) = state.grid[xy_to_i(x, y)] {
-                    draw_hexagon(framebuffer, x, y, spec);
+        for y in 0..state.grid.height() {
+            for x in 0..state.grid.width() {
+                match state.grid[state.grid.xy_to_i(x, y)] {
+                    GridCell::Present(spec) => draw_hexagon(framebuffer, &state.grid, x, y, spec),
+                    GridCell::Wall => draw_wall(framebuffer, &state.grid, x, y),
+                    GridCell::Absent | GridCell::Animating => {}
                 }
             }
         }
 
-        for index in state.cursor.iter() {
-            let (x, y) = i_to_xy(index);
-            let (p_x, p_y) = p_xy(x, y);
+        for fuse in state.fuses.iter() {
+            let (x, y) = state.grid.i_to_xy(fuse.index);
+            if fuse.frames_left <= FLASH_FRAMES {
+                let (p_x, p_y) = p_xy(&state.grid, x, y);
+                let (_, outline) = get_colours(fuse.spec);
+                if on_left!(x) {
+                    framebuffer.hexagon_left(p_x, p_y, WHITE, outline);
+                } else {
+                    framebuffer.hexagon_right(p_x, p_y, WHITE, outline);
+                }
+            } else {
+                draw_hexagon(framebuffer, &state.grid, x, y, fuse.spec);
+            }
+        }
+
+        if state.scramble_frames_left & 0b100 != 0 {
+            for &index in state.scrambled.iter() {
+                if let GridCell::Present(spec) = state.grid[index] {
+                    let (x, y) = state.grid.i_to_xy(index);
+                    let (p_x, p_y) = p_xy(&state.grid, x, y);
+                    let (inside, _) = get_colours(spec);
+                    if on_left!(x) {
+                        framebuffer.hexagon_left(p_x, p_y, inside, WHITE);
+                    } else {
+                        framebuffer.hexagon_right(p_x, p_y, inside, WHITE);
+                    }
+                }
+            }
+        }
+
+        for index in state.cursor.cells(&state.grid, &state.cursor_shape) {
+            let (x, y) = state.grid.i_to_xy(index);
+            let (p_x, p_y) = p_xy(&state.grid, x, y);
             framebuffer.draw_rect_with_shader(
                 p_x as usize - 1,
                 p_y as usize - 1,
@@ -703,6 +1380,32 @@ pub fn update_and_render(
             );
         }
 
+        let stuck_message: Option<&[u8]> = match state.stuck {
+            Some(Stuck::NoMatchingSwap) => Some(b"no matches left! press enter to reshuffle"),
+            Some(Stuck::OddColourCount) => {
+                Some(b"this cannot all be cleared! press enter to reshuffle")
+            }
+            None => None,
+        };
+        if let Some(message) = stuck_message {
+            framebuffer.print_line(message, 2, 248, WHITE_INDEX);
+        }
+
+        if let Some((c1, c2)) = hint {
+            let cells = Cursor::Selected(c1, c2).cells(&state.grid, &state.cursor_shape);
+            for &index in cells.iter() {
+                let (x, y) = state.grid.i_to_xy(index);
+                let (p_x, p_y) = p_xy(&state.grid, x, y);
+                framebuffer.draw_rect_with_shader(
+                    p_x as usize - 1,
+                    p_y as usize - 1,
+                    6,
+                    10,
+                    hint_shader(state.frame_counter),
+                );
+            }
+        }
+
         for anim in state.animations.iter() {
             if let &Animation {
                 x,
@@ -711,10 +1414,401 @@ pub fn update_and_render(
                 ..
             } = anim
             {
-                draw_hexagon(framebuffer, x, y, spec);
+                draw_hexagon(framebuffer, &state.grid, x, y, spec);
             }
         }
     }
 
     state.frame_counter += 1;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gravity::{fall, GravityMode, GravityPull};
+    use crate::level::parse_level;
+    use crate::rules::MECHANIC_KINDS;
+    use crate::solver::{has_even_colour_counts, settle};
+
+    #[test]
+    fn from_grid_accepts_any_grid_that_fits_on_the_screen() {
+        let mut grid = Grid::new(6, 3);
+        grid[0] = GridCell::Present(0);
+        grid[1] = GridCell::Present(0);
+        assert!(GameState::from_grid([1; 16], grid.clone(), 5, RuleSet::default()).is_ok());
+
+        assert_eq!(
+            GameState::from_grid([1; 16], Grid::new(6, 3), 18, RuleSet::default()).err(),
+            Some(GridError::CursorOutOfBounds(18))
+        );
+        assert_eq!(
+            GameState::from_grid([1; 16], Grid::new(42, 3), 0, RuleSet::default()).err(),
+            Some(GridError::TooBigForScreen(42, 3))
+        );
+
+        let mut animating = grid.clone();
+        animating[4] = GridCell::Animating;
+        assert_eq!(
+            GameState::from_grid([1; 16], animating, 5, RuleSet::default()).err(),
+            Some(GridError::AnimatingCell(4))
+        );
+
+        let mut odd = grid;
+        odd[2] = GridCell::Present(0x12);
+        assert_eq!(
+            GameState::from_grid([1; 16], odd, 5, RuleSet::default()).err(),
+            Some(GridError::OddColourCount(0x12))
+        );
+    }
+
+    #[test]
+    fn get_hex_index_stays_on_small_grids() {
+        let grid = Grid::new(4, 3);
+        // the right half of the top right hex
+        assert_eq!(grid.get_hex_index(3, Dir::Right), None);
+        assert_eq!(grid.get_hex_index(3, Dir::Up), None);
+        assert_eq!(grid.get_hex_index(3, Dir::Left), Some(2));
+        // every third row lines up, and there is nothing further left of the top left hex.
+        assert_eq!(grid.get_hex_index(0, Dir::Down), None);
+        assert_eq!(grid.get_hex_index(2, Dir::Down), Some(2 * 4 + 1));
+    }
+
+    fn frame_with(state: &mut GameState, gamepad: Button::Ty) {
+        let input = Input {
+            gamepad,
+            previous_gamepad: Button::Ty::empty(),
+        };
+        update_and_render(&mut Framebuffer::new(), state, input, &mut Speaker::new());
+    }
+
+    #[test]
+    fn the_cursor_can_cross_a_hex_on_a_grid_one_hex_wide() {
+        let mut grid = Grid::new(2, 4);
+        // Something to play with, so the round isn't already won.
+        for &i in [4, 6].iter() {
+            grid[i] = GridCell::Present(0x01);
+            grid[i + 1] = GridCell::Present(0x12);
+        }
+        let rules = MECHANIC_KINDS
+            .iter()
+            .fold(RuleSet::default(), |rules, &kind| rules.with(kind, false));
+        let mut state = GameState::from_grid([1; 16], grid, 0, rules).unwrap();
+
+        frame_with(&mut state, Button::Right);
+        assert_eq!(usize::from(state.cursor), 1);
+        // Going right again would wrap round onto the next row.
+        frame_with(&mut state, Button::Right);
+        assert_eq!(usize::from(state.cursor), 1);
+        frame_with(&mut state, Button::Left);
+        assert_eq!(usize::from(state.cursor), 0);
+
+        let grid = Grid::new(2, 4);
+        assert!(grid.steps_from(0).iter().all(|&steps| steps != usize::MAX));
+    }
+
+    #[test]
+    fn undo_during_a_swap_puts_the_grid_back_and_redo_finishes_it() {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        let start = grid.len() / 2 + GRID_WIDTH as usize / 2 - 2;
+        for (i, &spec) in [0x00, 0x11, 0x11, 0x00].iter().enumerate() {
+            grid[start + i] = GridCell::Present(spec);
+        }
+        for _ in 0..grid.len() {
+            if fall(&mut grid, GravityMode::PerCell, GravityPull::Center) == 0 {
+                break;
+            }
+        }
+        let cursor = grid.iter().position(|c| c.is_present()).unwrap();
+        let mut state =
+            GameState::from_grid([1; 16], grid.clone(), cursor, RuleSet::default()).unwrap();
+
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::Right);
+        frame_with(&mut state, Button::Right);
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::Ty::empty());
+        assert!(!state.animations.is_empty());
+
+        frame_with(&mut state, Button::B);
+        assert!(state.animations.is_empty());
+        assert_eq!(state.grid, grid);
+
+        frame_with(&mut state, Button::Select | Button::B);
+        assert_ne!(state.grid, grid);
+        assert!(state.grid.iter().all(|c| !c.is_animating()));
+    }
+
+    #[test]
+    fn putting_a_selection_down_leaves_nothing_to_undo() {
+        let mut grid = Grid::new(12, 9);
+        let top = grid.xy_to_i(2, 1);
+        let elsewhere = grid.xy_to_i(6, 5);
+        for &i in [top, elsewhere].iter() {
+            grid[i] = GridCell::Present(0x01);
+            grid[i + 1] = GridCell::Present(0x12);
+        }
+        let rules = MECHANIC_KINDS
+            .iter()
+            .fold(RuleSet::default(), |rules, &kind| rules.with(kind, false));
+        let mut state = GameState::from_grid([1; 16], grid.clone(), top, rules).unwrap();
+
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::Down);
+        frame_with(&mut state, Button::A);
+        while !state.animations.is_empty() {
+            frame_with(&mut state, Button::Ty::empty());
+        }
+        let swapped = state.grid.clone();
+        assert_ne!(swapped, grid);
+
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::A);
+        assert_eq!(state.grid, swapped);
+
+        // So the undo goes straight back past the real swap.
+        frame_with(&mut state, Button::B);
+        assert_eq!(state.grid, grid);
+    }
+
+    #[test]
+    fn walls_hold_things_up_and_cannot_be_selected() {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        // Two rows, since a step down can skip a row.
+        for y in 10..12 {
+            for x in 0..GRID_WIDTH {
+                let i = grid.xy_to_i(x, y);
+                grid[i] = GridCell::Wall;
+            }
+        }
+        let hex = grid.xy_to_i(20, 4);
+        for &(i, spec) in [(hex, 0x01), (hex + 1, 0x12), (0, 0x01), (1, 0x12)].iter() {
+            grid[i] = GridCell::Present(spec);
+        }
+
+        for &mode in [GravityMode::PerCell, GravityMode::RigidChunks].iter() {
+            let mut rules = RuleSet::default();
+            rules.gravity_mode = mode;
+            let mut settled = grid.clone();
+            settle(&mut settled, &rules);
+
+            assert_ne!(settled, grid);
+            for (i, cell) in settled.iter().enumerate() {
+                let (_, y) = settled.i_to_xy(i);
+                assert_eq!(cell.is_wall(), (10..12).contains(&y));
+                if cell.is_present() {
+                    assert!(y < 10);
+                }
+            }
+        }
+
+        let wall = grid.xy_to_i(20, 10);
+        let mut state = GameState::from_grid([1; 16], grid, wall, RuleSet::default()).unwrap();
+        frame_with(&mut state, Button::A);
+        assert!(matches!(state.cursor, Cursor::Unselected(_)));
+    }
+
+    #[test]
+    fn a_larger_cursor_swaps_its_whole_group_at_once() {
+        let mut grid = Grid::new(8, 6);
+        let top = grid.xy_to_i(2, 1);
+        let elsewhere = grid.xy_to_i(6, 5);
+        for &i in [top, elsewhere].iter() {
+            grid[i] = GridCell::Present(0x01);
+            grid[i + 1] = GridCell::Present(0x12);
+        }
+        // Nothing clears or falls, so we can see where everything went.
+        let rules = MECHANIC_KINDS
+            .iter()
+            .fold(RuleSet::default(), |rules, &kind| rules.with(kind, false));
+        let mut state = GameState::from_grid([1; 16], grid.clone(), top, rules).unwrap();
+        let pair = CursorShape::presets()[1].clone();
+        assert!(state.set_cursor_shape(pair.clone()));
+
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::Down);
+        frame_with(&mut state, Button::A);
+        while !state.animations.is_empty() {
+            frame_with(&mut state, Button::Ty::empty());
+        }
+
+        let below = grid.get_hex_index(top, Dir::Down).unwrap();
+        let to = pair.cells(&grid, below).unwrap();
+        assert_eq!(pair.cells(&grid, top), Some(vec![top, top + 1]));
+        assert_eq!(state.grid[to[0]], GridCell::Present(0x01));
+        assert_eq!(state.grid[to[1]], GridCell::Present(0x12));
+        assert!(state.grid[top].is_absent() && state.grid[top + 1].is_absent());
+        assert_eq!(state.grid[elsewhere], grid[elsewhere]);
+    }
+
+    #[test]
+    fn selecting_the_same_place_twice_puts_the_selection_down_without_using_a_move() {
+        let mut grid = Grid::new(12, 9);
+        let top = grid.xy_to_i(2, 1);
+        let elsewhere = grid.xy_to_i(6, 5);
+        for &i in [top, elsewhere].iter() {
+            grid[i] = GridCell::Present(0x01);
+            grid[i + 1] = GridCell::Present(0x12);
+        }
+        let mut rules = MECHANIC_KINDS
+            .iter()
+            .fold(RuleSet::default(), |rules, &kind| rules.with(kind, false));
+        rules.limit = Limit::Moves(1);
+        let mut state = GameState::from_grid([1; 16], grid.clone(), top, rules).unwrap();
+
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::A);
+        assert!(matches!(state.cursor, Cursor::Unselected(c) if c == top));
+        assert!(state.animations.is_empty());
+        assert_eq!(state.swap_count, 0);
+        for _ in 0..10 {
+            frame_with(&mut state, Button::Ty::empty());
+        }
+        assert_eq!(state.ending(), None);
+        assert_eq!(state.grid, grid);
+
+        // The one move there is can still be made.
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::Down);
+        frame_with(&mut state, Button::A);
+        while !state.animations.is_empty() {
+            frame_with(&mut state, Button::Ty::empty());
+        }
+        frame_with(&mut state, Button::Ty::empty());
+        assert_eq!(state.ending(), Some(Ending::OutOfMoves));
+    }
+
+    #[test]
+    fn running_out_of_moves_or_time_ends_the_round_until_start_tries_again() {
+        let mut grid = Grid::new(12, 9);
+        let top = grid.xy_to_i(2, 1);
+        let elsewhere = grid.xy_to_i(6, 5);
+        for &i in [top, elsewhere].iter() {
+            grid[i] = GridCell::Present(0x01);
+            grid[i + 1] = GridCell::Present(0x12);
+        }
+        let mut rules = MECHANIC_KINDS
+            .iter()
+            .fold(RuleSet::default(), |rules, &kind| rules.with(kind, false));
+        rules.limit = Limit::Moves(1);
+        let mut state = GameState::from_grid([1; 16], grid.clone(), top, rules).unwrap();
+
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::Down);
+        frame_with(&mut state, Button::A);
+        while !state.animations.is_empty() {
+            assert_eq!(state.ending(), None);
+            frame_with(&mut state, Button::Ty::empty());
+        }
+        frame_with(&mut state, Button::Ty::empty());
+        assert_eq!(state.ending(), Some(Ending::OutOfMoves));
+
+        let ended = state.grid.clone();
+        frame_with(&mut state, Button::A);
+        frame_with(&mut state, Button::Up);
+        frame_with(&mut state, Button::A);
+        assert_eq!(state.grid, ended);
+        assert!(state.animations.is_empty());
+
+        frame_with(&mut state, Button::Start);
+        assert_eq!(state.ending(), None);
+        assert_eq!(state.grid, grid);
+        assert_eq!(state.swap_count, 0);
+
+        let mut rules = state.rules.clone();
+        rules.limit = Limit::Frames(3);
+        let mut state = GameState::from_grid([1; 16], grid, top, rules).unwrap();
+        for _ in 0..2 {
+            frame_with(&mut state, Button::Ty::empty());
+        }
+        assert_eq!(state.ending(), None);
+        frame_with(&mut state, Button::Ty::empty());
+        assert_eq!(state.ending(), Some(Ending::OutOfTime));
+    }
+
+    #[test]
+    fn start_reshuffles_a_stuck_grid_and_undo_puts_it_back() {
+        let rules = MECHANIC_KINDS
+            .iter()
+            .fold(RuleSet::default(), |rules, &kind| rules.with(kind, false))
+            .with(MechanicKind::Matching, true);
+        let mut state = GameState::from_grid([1; 16], Grid::new(12, 9), 0, rules).unwrap();
+        let start = state.grid.xy_to_i(4, 4);
+        for (i, &spec) in [0x00, 0x11, 0x22, 0x33].iter().enumerate() {
+            state.grid[start + i] = GridCell::Present(spec);
+        }
+        let stuck = state.grid.clone();
+
+        frame_with(&mut state, Button::Ty::empty());
+        assert_eq!(state.stuck(), Some(Stuck::OddColourCount));
+
+        frame_with(&mut state, Button::Start);
+        assert_ne!(state.grid, stuck);
+        frame_with(&mut state, Button::Ty::empty());
+        assert_eq!(state.stuck(), None);
+
+        frame_with(&mut state, Button::B);
+        assert_eq!(state.grid, stuck);
+    }
+
+    #[test]
+    fn bombs_set_off_their_colour_in_the_middle_of_a_swap_too() {
+        let rules = MECHANIC_KINDS
+            .iter()
+            .fold(RuleSet::default(), |rules, &kind| rules.with(kind, false))
+            .with(MechanicKind::Matching, true)
+            .with(MechanicKind::Bombs, true);
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        let bomb = grid.xy_to_i(20, 30);
+        let (from, to) = (grid.xy_to_i(4, 10), grid.xy_to_i(30, 50));
+        for &(i, spec) in [
+            (bomb, 0x12 | BOMB),
+            (bomb + 1, 0x12),
+            (grid.xy_to_i(10, 40), 0x12),
+            (from, 0x12),
+            (to, 0x22),
+            (grid.xy_to_i(6, 20), 0x22),
+        ]
+        .iter()
+        {
+            grid[i] = GridCell::Present(spec);
+        }
+        let mut state = GameState::from_grid([1; 16], grid, 0, rules).unwrap();
+
+        // The bomb's hex matches on the first frame, while these two are on their way.
+        state.grid[from] = GridCell::Animating;
+        state.grid[to] = GridCell::Animating;
+        let swap = Some(1);
+        let animations = [(from, to, 0x12), (to, from, 0x22)];
+        for &(i1, i2, spec) in animations.iter() {
+            let animation = Animation::new(&state.grid, i1, i2, spec, swap);
+            state.animations.push(animation);
+        }
+
+        while !state.animations.is_empty() || !state.fuses.is_empty() {
+            frame_with(&mut state, Button::Ty::empty());
+        }
+        assert_eq!(state.grid[from], GridCell::Present(0x22));
+        assert_eq!(state.grid[to], GridCell::Absent);
+        assert!(state
+            .grid
+            .iter()
+            .all(|c| !matches!(*c, GridCell::Present(0x12))));
+        assert!(has_even_colour_counts(&state.grid));
+    }
+
+    #[test]
+    fn bombs_set_off_their_colour_even_where_they_cannot_reach() {
+        // A single row of hexes don't touch each other, so the bomb can't get to the others.
+        let grid = parse_level("a1 21 21 .. .. 21\n").unwrap();
+        let rules = RuleSet::default()
+            .with(MechanicKind::Matching, true)
+            .with(MechanicKind::Bombs, true);
+        let mut state = GameState::from_grid([1; 16], grid, 0, rules).unwrap();
+
+        while {
+            frame_with(&mut state, Button::Ty::empty());
+            !state.fuses.is_empty()
+        } {}
+        assert!(state.grid.iter().all(|c| c.is_absent()));
+    }
+}
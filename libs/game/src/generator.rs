@@ -0,0 +1,227 @@
+// Small puzzles with a known solution, built by filling a shape on a grid just big enough
+// for it with pairs of colours, then only keeping the results that the solver can clear.
+use crate::game::{fill_grid, Dir, Grid, HalfHexSpec, BOMB};
+use crate::rules::RuleSet;
+use crate::solver::{settle, solve, Swap};
+use rand::Rng;
+use std::cmp::max;
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PuzzleShape {
+    Rectangle,
+    Diamond,
+    Cross,
+    // Roughly two thirds of the rectangle, picked at random.
+    Scattered,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PuzzleSpec {
+    // in whole hexes
+    pub width: u8,
+    // in rows
+    pub height: u8,
+    pub shape: PuzzleShape,
+    pub colours: u8,
+    // The number of pairs with a colour bomb in one half, (see `bombs.rs`.)
+    pub bombs: u8,
+    // The number of swaps in the shortest solution.
+    pub difficulty: usize,
+}
+
+pub struct Puzzle {
+    // This has already settled, so the solution can be played on it right away.
+    pub grid: Grid,
+    pub solution: Vec<Swap>,
+}
+
+const MAX_ATTEMPTS: usize = 64;
+
+// There are four palette entries each for the inside and the outline, (see `get_colours`.)
+const DISTINCT_COLOURS: u8 = 16;
+
+/// The puzzle is solvable under `rules`, but might not be under other rules.
+pub fn generate_puzzle<R: Rng>(rng: &mut R, spec: &PuzzleSpec, rules: &RuleSet) -> Option<Puzzle> {
+    for _ in 0..MAX_ATTEMPTS {
+        let mut grid = generate_grid(rng, spec);
+        settle(&mut grid, rules);
+
+        if let Some(solution) = solve(&grid, spec.difficulty, rules) {
+            if solution.len() == spec.difficulty {
+                return Some(Puzzle { grid, solution });
+            }
+        }
+    }
+
+    None
+}
+
+fn generate_grid<R: Rng>(rng: &mut R, spec: &PuzzleSpec) -> Grid {
+    let (width, height) = grid_dimensions(spec);
+    let shape = connected_shape(rng, spec);
+
+    let mut palette: Vec<HalfHexSpec> = (0..DISTINCT_COLOURS)
+        .map(|c| (c & 0b11) | (c >> 2) << 4)
+        .collect();
+    rng.shuffle(&mut palette);
+    palette.truncate(spec.colours.clamp(1, DISTINCT_COLOURS) as usize);
+
+    let pair_count = shape.iter().filter(|&&in_shape| in_shape).count() / 2;
+    // A bomb is the same colour as a plain half-hex, so swapping one in keeps the colour
+    // counts even.
+    let mut specs: Vec<HalfHexSpec> = (0..pair_count)
+        .flat_map(|i| {
+            let c = palette[i % palette.len()];
+            let first = if i < spec.bombs as usize { c | BOMB } else { c };
+            vec![first, c]
+        })
+        .collect();
+    rng.shuffle(&mut specs);
+
+    let index_of = |x: u8, y: u8| y as usize * width as usize + x as usize;
+    fill_grid(
+        rng,
+        (width, height),
+        |x, y| shape[index_of(x, y)],
+        || specs.pop().unwrap_or_default(),
+    )
+}
+
+// In half-hexes and rows, like `Grid`.
+fn grid_dimensions(spec: &PuzzleSpec) -> (u8, u8) {
+    (spec.width.clamp(1, 127) * 2, spec.height.max(1))
+}
+
+// The cells of the requested shape that can be reached from the one nearest the center,
+// less one if that would leave an odd number of cells.
+fn connected_shape<R: Rng>(rng: &mut R, spec: &PuzzleSpec) -> Vec<bool> {
+    let (width, height) = grid_dimensions(spec);
+    // Only used for its indexing and movement.
+    let grid = Grid::new(width, height);
+
+    let hex_width = width / 2;
+    let band = |length: u8, i: u8| {
+        let band_length = max(length / 3, 1);
+        let start = (length - band_length) / 2;
+        i >= start && i < start + band_length
+    };
+
+    let mut shape = vec![false; grid.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let (hex_x, row) = (x / 2, y);
+            shape[grid.xy_to_i(x, y)] = match spec.shape {
+                PuzzleShape::Rectangle => true,
+                PuzzleShape::Diamond => {
+                    let dx = (2 * hex_x + 1).abs_diff(hex_width) as usize * height as usize;
+                    let dy = (2 * row + 1).abs_diff(height) as usize * hex_width as usize;
+                    dx + dy <= hex_width as usize * height as usize
+                }
+                PuzzleShape::Cross => band(hex_width, hex_x) || band(height, row),
+                PuzzleShape::Scattered => rng.gen_range(0, 3) != 0,
+            };
+        }
+    }
+
+    let center = (0..grid.len()).filter(|&i| shape[i]).min_by_key(|&i| {
+        let (x, y) = grid.i_to_xy(i);
+        x.abs_diff(width / 2) as usize + y.abs_diff(height / 2) as usize
+    });
+
+    let mut connected = vec![false; grid.len()];
+    let mut last_reached = None;
+    if let Some(center) = center {
+        let mut queue = VecDeque::new();
+        connected[center] = true;
+        queue.push_back(center);
+        while let Some(index) = queue.pop_front() {
+            last_reached = Some(index);
+            for &dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter() {
+                if let Some(i) = grid.get_hex_index(index, dir) {
+                    if shape[i] && !connected[i] {
+                        connected[i] = true;
+                        queue.push_back(i);
+                    }
+                }
+            }
+        }
+    }
+
+    // The last cell reached cannot be the only link to any other cell, so we can take it away.
+    if connected.iter().filter(|&&c| c).count() % 2 == 1 {
+        if let Some(index) = last_reached {
+            connected[index] = false;
+        }
+    }
+
+    connected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{is_bomb, GridCell};
+    use crate::solver::{apply_swap, has_even_colour_counts};
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn generated_puzzles_are_cleared_by_their_solutions() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        for &(shape, width, height) in [
+            // Since matches resolve all at once, a 2 by 3 rectangle never takes four swaps.
+            (PuzzleShape::Rectangle, 2, 4),
+            (PuzzleShape::Diamond, 3, 5),
+            (PuzzleShape::Cross, 3, 5),
+        ]
+        .iter()
+        {
+            let spec = PuzzleSpec {
+                width,
+                height,
+                shape,
+                colours: 3,
+                bombs: 0,
+                difficulty: 4,
+            };
+
+            let rules = RuleSet::default();
+            let puzzle = generate_puzzle(&mut rng, &spec, &rules).expect("should find a puzzle");
+            assert_eq!(puzzle.solution.len(), spec.difficulty);
+
+            let mut grid = puzzle.grid;
+            for &swap in puzzle.solution.iter() {
+                apply_swap(&mut grid, swap, &rules);
+            }
+            assert!(grid.iter().all(|c| c.is_absent()));
+        }
+    }
+
+    #[test]
+    fn puzzles_with_bombs_keep_even_colour_counts_all_the_way_through() {
+        let mut rng = XorShiftRng::from_seed([9; 16]);
+        let spec = PuzzleSpec {
+            width: 3,
+            height: 4,
+            shape: PuzzleShape::Rectangle,
+            colours: 3,
+            bombs: 2,
+            difficulty: 2,
+        };
+
+        let rules = RuleSet::default();
+        let puzzle = generate_puzzle(&mut rng, &spec, &rules).expect("should find a puzzle");
+
+        let mut grid = puzzle.grid;
+        assert!(grid
+            .iter()
+            .any(|c| matches!(*c, GridCell::Present(s) if is_bomb(s))));
+        assert!(has_even_colour_counts(&grid));
+        for &swap in puzzle.solution.iter() {
+            apply_swap(&mut grid, swap, &rules);
+            assert!(has_even_colour_counts(&grid));
+        }
+        assert!(grid.iter().all(|c| c.is_absent()));
+    }
+}
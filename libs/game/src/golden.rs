@@ -0,0 +1,160 @@
+// Golden-image tests: known `GameState`s are rendered through `update_and_render`, and the
+// frame compared against a reference image in `golden/`. These catch drawing mistakes, like
+// half-hexes that don't line up, that no other test would notice.
+//
+// On a mismatch, the frame that was drawn and a diff against the reference are written to
+// `target/golden/`. In the diff, pixels that match are a dim copy of the reference and pixels
+// that don't are magenta. If the change was on purpose, run the tests with `UPDATE_GOLDEN` set
+// to write new references, then look at them before committing them.
+//
+// The images are written with the same PPM encoder as the headless runner's screenshots, so a
+// screenshot of the same state can be dropped in as a reference.
+use crate::game::{
+    update_and_render, GameState, Grid, GridCell, HalfHexSpec, GRID_HEIGHT, GRID_WIDTH,
+};
+use crate::ppm::{from_ppm, to_ppm};
+use crate::rules::{RuleSet, MECHANIC_KINDS};
+use platform_types::{Button, Input, Speaker};
+use rendering::Framebuffer;
+use std::fs;
+use std::path::PathBuf;
+
+const GOLDEN_SEED: [u8; 16] = [
+    0x6f, 0x3a, 0xd1, 0x08, 0x9c, 0x42, 0xe7, 0x15, 0xb0, 0x5d, 0x23, 0x8e, 0xf4, 0x61, 0x37, 0xca,
+];
+
+const DIFF_COLOUR: u32 = 0xFFFF00FF;
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("golden")
+        .join(format!("{}.ppm", name))
+}
+
+fn output_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("golden")
+        .join(format!("{}.ppm", name))
+}
+
+fn diff(expected: &Framebuffer, actual: &Framebuffer) -> (Framebuffer, usize) {
+    let mut count = 0;
+    let buffer = expected
+        .buffer
+        .iter()
+        .zip(actual.buffer.iter())
+        .map(|(&e, &a)| {
+            if e == a {
+                // A quarter of each channel.
+                0xFF00_0000 | (e >> 2) & 0x003F_3F3F
+            } else {
+                count += 1;
+                DIFF_COLOUR
+            }
+        })
+        .collect();
+
+    (Framebuffer { buffer }, count)
+}
+
+fn write(path: &PathBuf, framebuffer: &Framebuffer) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, to_ppm(&framebuffer.buffer)).unwrap();
+}
+
+fn assert_matches_golden(name: &str, actual: &Framebuffer) {
+    let path = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write(&path, actual);
+        return;
+    }
+
+    let expected = fs::read(&path)
+        .ok()
+        .and_then(|ppm| from_ppm(&ppm))
+        .map(|buffer| Framebuffer { buffer });
+    let expected = match expected {
+        Some(expected) => expected,
+        None => panic!(
+            "{} is missing or unreadable. Run with UPDATE_GOLDEN set to write it.",
+            path.display()
+        ),
+    };
+
+    if expected != *actual {
+        let (diff, count) = diff(&expected, actual);
+        let actual_path = output_path(&format!("{}.actual", name));
+        let diff_path = output_path(&format!("{}.diff", name));
+        write(&actual_path, actual);
+        write(&diff_path, &diff);
+        panic!(
+            "{} pixels of {} differ from {}. See {} and {}.",
+            count,
+            name,
+            path.display(),
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn render(state: &mut GameState, frames: usize) -> Framebuffer {
+    let mut framebuffer = Framebuffer::new();
+    for _ in 0..frames {
+        let input = Input {
+            gamepad: Button::Ty::empty(),
+            previous_gamepad: Button::Ty::empty(),
+        };
+        update_and_render(&mut framebuffer, state, input, &mut Speaker::new());
+    }
+    framebuffer
+}
+
+// Every half-hex spec from 0 to 0x3F, which covers every kind and every plain colour pair, in
+// order, over and over, with a run of walls and a run of empty cells. Nothing is switched on,
+// so it all stays where it is.
+fn every_half_hex(width: u8, height: u8) -> GameState {
+    let mut grid = Grid::new(width, height);
+    for i in 0..grid.len() {
+        grid[i] = match i / 64 {
+            1 => GridCell::Wall,
+            3 => GridCell::Absent,
+            _ => GridCell::Present((i % 64) as HalfHexSpec),
+        };
+    }
+
+    let rules = MECHANIC_KINDS
+        .iter()
+        .fold(RuleSet::default(), |rules, &kind| rules.with(kind, false));
+
+    GameState::from_grid(GOLDEN_SEED, grid, 0, rules).unwrap()
+}
+
+#[test]
+fn a_new_game_looks_the_same() {
+    let mut state = GameState::new(GOLDEN_SEED, RuleSet::default());
+    assert_matches_golden("new_game", &render(&mut state, 1));
+}
+
+#[test]
+fn every_half_hex_lines_up_on_every_kind_of_row() {
+    let mut state = every_half_hex(GRID_WIDTH, GRID_HEIGHT);
+    assert_matches_golden("every_half_hex", &render(&mut state, 1));
+
+    // A small grid ends up in a different place on the screen.
+    let mut state = every_half_hex(12, 8);
+    assert_matches_golden("every_half_hex_small", &render(&mut state, 1));
+}
+
+#[test]
+fn diffs_mark_the_pixels_that_changed() {
+    let expected = Framebuffer::new();
+    let mut actual = Framebuffer::new();
+    actual.buffer[3] = !expected.buffer[3] | 0xFF00_0000;
+
+    let (diff, count) = diff(&expected, &actual);
+    assert_eq!(count, 1);
+    assert_eq!(diff.buffer[3], DIFF_COLOUR);
+    assert_ne!(diff.buffer[4], DIFF_COLOUR);
+}
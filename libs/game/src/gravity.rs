@@ -0,0 +1,311 @@
+// Gravity, which pulls towards one of a few places, (see `GravityPull`,) and either moves each
+// half-hex on its own or moves connected groups of half-hexes together, as if they were glued.
+//
+// Moving every half-hex in a group one step in the same `Dir` would not keep the group's shape,
+// since what a step in a given direction means depends on which row type, and which side of a
+// hex, a half-hex is on. Instead, a group moves by one of the translations of the whole grid that
+// keep every half-hex on the same side of its hex, and every gap between half-hexes the same on
+// screen. A group takes whichever of those translations brings it closest to where it is being
+// pulled, as long as every half-hex in it has somewhere empty to go.
+use crate::game::{fall_once, Dir, Grid, GridCell, ROW_TYPES};
+use std::collections::VecDeque;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GravityMode {
+    // Each half-hex falls on its own.
+    #[default]
+    PerCell,
+    // Half-hexes that touch fall together.
+    RigidChunks,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GravityPull {
+    #[default]
+    Center,
+    // Towards the bottom of the screen.
+    Down,
+    // Towards the half-hex at this index. An index that is off the grid pulls nowhere.
+    Attractor(usize),
+    // Nothing falls.
+    Off,
+}
+
+// Moves everything that can fall one step, and returns how many half-hexes moved.
+pub(crate) fn fall(grid: &mut Grid, mode: GravityMode, pull: GravityPull) -> usize {
+    let pull = match pull {
+        GravityPull::Attractor(index) if index >= grid.len() => GravityPull::Off,
+        pull => pull,
+    };
+
+    match (mode, pull) {
+        (_, GravityPull::Off) => 0,
+        (GravityMode::PerCell, GravityPull::Center) => {
+            fall_once(grid, (grid.width() as i16 / 2, grid.height() as i16 / 2))
+        }
+        (GravityMode::PerCell, GravityPull::Attractor(index)) => {
+            let (x, y) = grid.i_to_xy(index);
+            fall_once(grid, (x as i16, y as i16))
+        }
+        // Every half-hex on its own is a group of one.
+        (GravityMode::PerCell, GravityPull::Down) => {
+            let groups = (0..grid.len()).filter(|&i| grid[i].is_present());
+            let groups: Vec<Vec<usize>> = groups.map(|i| vec![i]).collect();
+            move_groups(grid, groups, pull)
+        }
+        (GravityMode::RigidChunks, _) => {
+            let groups = connected_groups(grid);
+            move_groups(grid, groups, pull)
+        }
+    }
+}
+
+// Each is a change in row, and a change in `x` that is then adjusted per row type, (see
+// `translate`,) so the on-screen offset is the same for every half-hex.
+const TRANSLATIONS: [(i16, i16); 6] = [(0, 2), (0, -2), (1, 0), (1, -2), (-1, 0), (-1, 2)];
+
+// Where on screen the half-hex is, in the units `p_xy` uses, minus the edge offset.
+fn pixel_xy(x: u8, y: u8) -> (i32, i32) {
+    let x_offset = (y % ROW_TYPES) as i32 * 4 - if x & 1 == 0 { 0 } else { 2 };
+    (x as i32 * 6 + x_offset, y as i32 * 4)
+}
+
+pub(crate) fn translate(grid: &Grid, index: usize, (d_y, d_x): (i16, i16)) -> Option<usize> {
+    let (x, y) = grid.i_to_xy(index);
+    let row_type = y % ROW_TYPES;
+    // Moving between row types changes the per-row offset in `pixel_xy`, so this makes up for it.
+    let d_x = d_x
+        + match (d_y, row_type) {
+            (1, 2) => 2,
+            (-1, 0) => -2,
+            _ => 0,
+        };
+
+    let (new_x, new_y) = (x as i16 + d_x, y as i16 + d_y);
+    if new_x < 0 || new_y < 0 || new_x >= grid.width() as i16 || new_y >= grid.height() as i16 {
+        None
+    } else {
+        Some(grid.xy_to_i(new_x as u8, new_y as u8))
+    }
+}
+
+fn connected_groups(grid: &Grid) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; grid.len()];
+    let mut groups = Vec::new();
+    for start in 0..grid.len() {
+        if seen[start] || !grid[start].is_present() {
+            continue;
+        }
+
+        let mut group = Vec::new();
+        let mut queue = VecDeque::new();
+        seen[start] = true;
+        queue.push_back(start);
+        while let Some(index) = queue.pop_front() {
+            group.push(index);
+            for &dir in [Dir::Up, Dir::Down, Dir::Left, Dir::Right].iter() {
+                if let Some(i) = grid.get_hex_index(index, dir) {
+                    if !seen[i] && grid[i].is_present() {
+                        seen[i] = true;
+                        queue.push_back(i);
+                    }
+                }
+            }
+        }
+        groups.push(group);
+    }
+
+    groups
+}
+
+/// Moves every group that can get closer to where `pull` pulls one step, and returns how many
+/// half-hexes moved. Every move makes the total of `distance` over the grid smaller, so this
+/// always stops moving things eventually, rather than going back and forth.
+fn move_groups(grid: &mut Grid, groups: Vec<Vec<usize>>, pull: GravityPull) -> usize {
+    let target = match pull {
+        GravityPull::Center => {
+            let (last_x, last_y) = (grid.width() - 1, grid.height() - 1);
+            // The last half-hex in a row is a right half, and the furthest right ones are in the
+            // third row type, if there is one.
+            let (max_x, _) = pixel_xy(last_x, last_y.min(ROW_TYPES - 1));
+            let (_, max_y) = pixel_xy(last_x, last_y);
+            Some((max_x / 2, max_y / 2))
+        }
+        GravityPull::Attractor(index) => {
+            let (x, y) = grid.i_to_xy(index);
+            Some(pixel_xy(x, y))
+        }
+        GravityPull::Down => None,
+        GravityPull::Off => return 0,
+    };
+    let distance = |grid: &Grid, index: usize| {
+        let (x, y) = grid.i_to_xy(index);
+        let (p_x, p_y) = pixel_xy(x, y);
+        match target {
+            Some((target_x, target_y)) => {
+                let (d_x, d_y) = ((p_x - target_x) as i64, (p_y - target_y) as i64);
+                d_x * d_x + d_y * d_y
+            }
+            None => -(p_y as i64),
+        }
+    };
+
+    let mut moved = 0;
+    let mut in_group = vec![false; grid.len()];
+    for group in groups {
+        for &i in group.iter() {
+            in_group[i] = true;
+        }
+
+        // Both ways down one row are just as far down, but one goes a bit right and the other
+        // further left, so this takes the left one every third row to keep things falling
+        // straight overall.
+        let mut translations = TRANSLATIONS;
+        let (_, y) = grid.i_to_xy(group[0]);
+        if target.is_none() && y % ROW_TYPES == ROW_TYPES - 1 {
+            translations.swap(2, 3);
+        }
+
+        let current: i64 = group.iter().map(|&i| distance(grid, i)).sum();
+        let mut best: Option<(i64, Vec<usize>)> = None;
+        for &translation in translations.iter() {
+            let targets: Option<Vec<usize>> = group
+                .iter()
+                .map(|&i| translate(grid, i, translation))
+                .map(|target| target.filter(|&t| in_group[t] || grid[t].is_absent()))
+                .collect();
+
+            if let Some(targets) = targets {
+                let total: i64 = targets.iter().map(|&i| distance(grid, i)).sum();
+                if total < best.as_ref().map_or(current, |b| b.0) {
+                    best = Some((total, targets));
+                }
+            }
+        }
+
+        if let Some((_, targets)) = best {
+            let cells: Vec<GridCell<_>> = group.iter().map(|&i| grid[i]).collect();
+            for &i in group.iter() {
+                grid[i] = GridCell::Absent;
+            }
+            for (&target, cell) in targets.iter().zip(cells) {
+                grid[target] = cell;
+            }
+            moved += group.len();
+        }
+
+        for &i in group.iter() {
+            in_group[i] = false;
+        }
+    }
+
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GRID_HEIGHT, GRID_WIDTH};
+    use rand::prng::XorShiftRng;
+    use rand::{Rng, SeedableRng};
+    use std::collections::HashSet;
+
+    fn shape(grid: &Grid) -> Vec<(i32, i32, u8)> {
+        let present: Vec<usize> = (0..grid.len()).filter(|&i| grid[i].is_present()).collect();
+        let (x0, y0) = grid.i_to_xy(present[0]);
+        let (p_x0, p_y0) = pixel_xy(x0, y0);
+        present
+            .iter()
+            .map(|&i| {
+                let (x, y) = grid.i_to_xy(i);
+                let (p_x, p_y) = pixel_xy(x, y);
+                match grid[i] {
+                    GridCell::Present(spec) => (p_x - p_x0, p_y - p_y0, spec),
+                    _ => unreachable!(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunks_keep_their_shape_all_the_way_to_the_middle() {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        // Three whole hexes touching each other, in the top left.
+        let first = grid.xy_to_i(6, 6);
+        let beside = grid.get_hex_index(first + 1, Dir::Right).unwrap();
+        let below = grid.get_hex_index(first, Dir::Down).unwrap();
+        for (i, &index) in [first, beside, below].iter().enumerate() {
+            let left = index & !1;
+            grid[left] = GridCell::Present(i as u8 * 2);
+            grid[left + 1] = GridCell::Present(i as u8 * 2 + 1);
+        }
+        assert_eq!(connected_groups(&grid).len(), 1);
+        let before = shape(&grid);
+
+        let mut steps = 0;
+        while fall(&mut grid, GravityMode::RigidChunks, GravityPull::Center) > 0 {
+            assert_eq!(shape(&grid), before);
+            steps += 1;
+            assert!(steps < grid.len());
+        }
+
+        let (x, y) = grid.i_to_xy(grid.iter().position(|c| c.is_present()).unwrap());
+        assert!(x.abs_diff(GRID_WIDTH / 2) <= 4 && y.abs_diff(GRID_HEIGHT / 2) <= 4);
+    }
+
+    #[test]
+    fn every_pull_settles_without_going_back_and_forth_on_even_and_odd_sized_grids() {
+        let mut rng = XorShiftRng::from_seed([7; 16]);
+        // An odd and an even number of hexes across, and of rows.
+        for &(width, height) in [(10, 9), (12, 9), (10, 10), (12, 10)].iter() {
+            let len = width as usize * height as usize;
+            let mut pulls = vec![GravityPull::Center, GravityPull::Down, GravityPull::Off];
+            pulls.extend((0..len).map(GravityPull::Attractor));
+
+            for &mode in [GravityMode::PerCell, GravityMode::RigidChunks].iter() {
+                for &pull in pulls.iter() {
+                    let mut grid = Grid::new(width, height);
+                    for i in 0..len {
+                        if rng.gen_bool(0.5) {
+                            grid[i] = GridCell::Present(rng.gen::<u8>() & 0b0011_0011);
+                        }
+                    }
+                    let count = grid.iter().filter(|c| c.is_present()).count();
+
+                    let mut seen = HashSet::new();
+                    while fall(&mut grid, mode, pull) > 0 {
+                        assert!(
+                            seen.insert(grid.clone()),
+                            "{:?} {:?} went back and forth on {}x{}",
+                            mode,
+                            pull,
+                            width,
+                            height
+                        );
+                    }
+                    assert_eq!(grid.iter().filter(|c| c.is_present()).count(), count);
+                    if pull == GravityPull::Off {
+                        assert!(seen.is_empty());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn down_pulls_things_to_the_bottom_row() {
+        let mut grid = Grid::new(12, 9);
+        let top = grid.xy_to_i(4, 0);
+        grid[top] = GridCell::Present(0x01);
+        grid[top + 1] = GridCell::Present(0x10);
+
+        for &mode in [GravityMode::PerCell, GravityMode::RigidChunks].iter() {
+            let mut grid = grid.clone();
+            while fall(&mut grid, mode, GravityPull::Down) > 0 {}
+
+            let present: Vec<usize> = (0..grid.len()).filter(|&i| grid[i].is_present()).collect();
+            assert_eq!(present.len(), 2);
+            assert!(present.iter().all(|&i| grid.i_to_xy(i).1 == 8));
+        }
+    }
+}
@@ -0,0 +1,158 @@
+// Hints: a swap that makes a match straight away, for when there's no obvious move left.
+//
+// Both halves of a match end up in the same hex, so a swap makes a match exactly when it puts
+// a half-hex beside one that it matches. Looking for only that, rather than trying every swap
+// and running the rules, is fast enough to do every frame, even on the largest grids.
+//
+// With a larger cursor, a swap moves whole groups, so only half-hexes that sit in the same
+// place in two groups can trade places, and whatever else moves with them can change what
+// ends up beside them.
+use crate::cursor_shape::CursorShape;
+use crate::game::{Grid, GridCell, HalfHexSpec};
+use crate::matching::{halves_match, resolve_matches};
+use crate::solver::Swap;
+use std::collections::BTreeMap;
+
+/// A swap that `shape` can make, given as the two anchors, that makes at least one match, with
+/// the cell it moves a half-hex to as few steps from `near` as possible. `grid` should be the
+/// grid as it will be once everything in flight has landed.
+pub fn find_hint(grid: &Grid, shape: &CursorShape, near: usize) -> Option<Swap> {
+    let mut grid = grid.clone();
+    // Anything that already matches is about to be cleared, so it can't be part of a hint.
+    resolve_matches(&mut grid);
+
+    // The group at each anchor, if it can be picked up, and for each cell, which groups cover
+    // it, and where in them.
+    let groups: Vec<Option<Vec<usize>>> = (0..grid.len())
+        .map(|anchor| {
+            shape.cells(&grid, anchor).filter(|cells| {
+                cells
+                    .iter()
+                    .all(|&i| !grid[i].is_wall() && !grid[i].is_animating())
+            })
+        })
+        .collect();
+    let mut covering: Vec<Vec<(usize, usize)>> = vec![Vec::new(); grid.len()];
+    for (anchor, cells) in groups.iter().enumerate() {
+        for (place, &i) in cells.iter().flatten().enumerate() {
+            covering[i].push((anchor, place));
+        }
+    }
+
+    let mut by_spec: BTreeMap<HalfHexSpec, Vec<usize>> = BTreeMap::new();
+    for (i, cell) in grid.iter().enumerate() {
+        if let GridCell::Present(spec) = *cell {
+            by_spec.entry(spec).or_default().push(i);
+        }
+    }
+
+    let steps = grid.steps_from(near);
+    let mut targets: Vec<usize> = (0..grid.len())
+        .filter(|&i| !grid[i].is_wall() && grid[grid.partner_index(i)].is_present())
+        .collect();
+    targets.sort_by_key(|&i| steps[i]);
+
+    for target in targets {
+        let partner = grid.partner_index(target);
+        let beside = match grid[partner] {
+            GridCell::Present(spec) => spec,
+            _ => continue,
+        };
+
+        let swap = by_spec
+            .iter()
+            .filter(|&(&spec, _)| halves_match(spec, beside))
+            .flat_map(|(_, cells)| cells.iter().cloned())
+            .flat_map(|from| covering[from].iter())
+            .flat_map(|&(from, place)| {
+                covering[target]
+                    .iter()
+                    .filter(move |&&(_, to_place)| to_place == place)
+                    .map(move |&(to, _)| (from, to))
+            })
+            .find(|&(from, to)| match (&groups[from], &groups[to]) {
+                (Some(from), Some(to)) => makes_match(&grid, from, to),
+                _ => false,
+            });
+        if swap.is_some() {
+            return swap;
+        }
+    }
+
+    None
+}
+
+// Whether swapping the groups `from` and `to`, cell by cell, puts any half-hex beside one it
+// matches.
+fn makes_match(grid: &Grid, from: &[usize], to: &[usize]) -> bool {
+    if from == to || from.iter().any(|i| to.contains(i)) {
+        return false;
+    }
+
+    let moved: Vec<(usize, GridCell<HalfHexSpec>)> = from
+        .iter()
+        .zip(to.iter())
+        .flat_map(|(&i1, &i2)| vec![(i2, grid[i1]), (i1, grid[i2])])
+        .collect();
+    let after = |i: usize| {
+        moved
+            .iter()
+            .find(|&&(j, _)| j == i)
+            .map_or(grid[i], |&(_, cell)| cell)
+    };
+
+    moved
+        .iter()
+        .any(|&(i, cell)| match (cell, after(grid.partner_index(i))) {
+            (GridCell::Present(h1), GridCell::Present(h2)) => halves_match(h1, h2),
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GRID_HEIGHT, GRID_WIDTH};
+    use crate::matching::resolve_matches_at;
+
+    #[test]
+    fn hints_make_a_match_near_the_cursor_and_skip_what_is_about_to_clear() {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        // About to clear, so no help.
+        let matching = grid.xy_to_i(4, 4);
+        grid[matching] = GridCell::Present(0x01);
+        grid[matching + 1] = GridCell::Present(0x01);
+        // Two hexes that each need a half from the other, one far from the cursor.
+        let near = grid.xy_to_i(20, 30);
+        let far = grid.xy_to_i(30, 50);
+        grid[near] = GridCell::Present(0x02);
+        grid[near + 1] = GridCell::Present(0x13);
+        grid[far] = GridCell::Present(0x13);
+        grid[far + 1] = GridCell::Present(0x02);
+
+        let single = CursorShape::single();
+        let (from, to) = find_hint(&grid, &single, near - 4).expect("there is a hint");
+        assert!([near, near + 1].contains(&to));
+        assert!([far, far + 1].contains(&from));
+
+        let mut after = grid.clone();
+        after.swap(from, to);
+        assert!(!resolve_matches_at(&mut after, vec![from, to]).is_empty());
+        assert_eq!(find_hint(&after, &single, near), None);
+
+        // Each of these shapes can only make some of the swaps a single cursor can.
+        for shape in CursorShape::presets().iter().skip(1) {
+            let (from, to) = find_hint(&grid, shape, near - 4).expect("there is a hint");
+            let from = shape.cells(&grid, from).expect("fits");
+            let to = shape.cells(&grid, to).expect("fits");
+            assert!(from.iter().all(|i| !to.contains(i)));
+
+            let mut after = grid.clone();
+            for (&i1, &i2) in from.iter().zip(to.iter()) {
+                after.swap(i1, i2);
+            }
+            let moved: Vec<usize> = from.iter().chain(to.iter()).cloned().collect();
+            assert!(!resolve_matches_at(&mut after, moved).is_empty());
+        }
+    }
+}
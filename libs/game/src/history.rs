@@ -0,0 +1,90 @@
+// Snapshots of the game from before each swap, so swaps can be undone and redone.
+
+// Each snapshot of a full size grid is a few kilobytes, so we don't keep them forever.
+const MAX_SNAPSHOTS: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct History<T> {
+    undo: Vec<T>,
+    redo: Vec<T>,
+}
+
+impl<T> Default for History<T> {
+    fn default() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+        }
+    }
+}
+
+impl<T> History<T> {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Call this with the snapshot from just before a swap.
+    pub fn record(&mut self, snapshot: T) {
+        if self.undo.len() >= MAX_SNAPSHOTS {
+            self.undo.remove(0);
+        }
+        self.undo.push(snapshot);
+        self.redo.clear();
+    }
+
+    /// Returns the snapshot to go back to, if there is one. `current` is kept so it can be
+    /// redone.
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let previous = self.undo.pop()?;
+        self.redo.push(current);
+        Some(previous)
+    }
+
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let next = self.redo.pop()?;
+        self.undo.push(current);
+        Some(next)
+    }
+
+    // Both lists of snapshots, oldest first, for saving.
+    pub(crate) fn snapshots(&self) -> (&[T], &[T]) {
+        (&self.undo, &self.redo)
+    }
+
+    pub(crate) fn from_snapshots(undo: Vec<T>, redo: Vec<T>) -> Self {
+        History { undo, redo }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{Grid, GridCell};
+
+    fn grid_with(spec: u8) -> Grid {
+        let mut grid = Grid::new(2, 1);
+        grid[0] = GridCell::Present(spec);
+        grid
+    }
+
+    #[test]
+    fn undo_then_redo_walks_back_and_forth_and_a_new_swap_drops_the_redos() {
+        let mut history = History::new();
+        history.record(grid_with(0));
+        history.record(grid_with(1));
+
+        assert_eq!(history.undo(grid_with(2)), Some(grid_with(1)));
+        assert_eq!(history.undo(grid_with(1)), Some(grid_with(0)));
+        assert_eq!(history.undo(grid_with(0)), None);
+        assert_eq!(history.redo(grid_with(0)), Some(grid_with(1)));
+
+        history.record(grid_with(1));
+        assert_eq!(history.redo(grid_with(3)), None);
+        assert_eq!(history.undo(grid_with(3)), Some(grid_with(1)));
+    }
+}
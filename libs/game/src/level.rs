@@ -0,0 +1,275 @@
+// A plain-text level format, meant to be written by hand and diffed in git.
+//
+// Each line is one row of the grid, and each row holds the same, even, number of half-hexes,
+// left to right, using the same doubled-width indexing as `design/gridMovement.md`: a whole hex
+// is a left half at an even `x` followed by a right half at the odd `x` after it. The size of
+// the grid is however many rows there are, by however many half-hexes are in them.
+//
+// Every half-hex is a pair of characters:
+//  * `..` is an absent half-hex.
+//  * `~~` is an animating one. These are only here so any `Grid` can be written out, they
+//    cannot be loaded into a `GameState`.
+//  * `##` is a wall, which never moves and cannot be swapped.
+//  * Two hex digits describe a present half-hex. The first is the low nibble of the
+//    `HalfHexSpec`, which holds the inside colour, and the second is the high nibble, which
+//    holds the outline colour. `0` to `3` are blue, green, red and yellow, (see `get_colours`.)
+//    A first digit from `4` to `7` makes a multi-colour half-hex, with its outline colour in the
+//    first digit, and its set of inside colours in the second, (see `MULTI_COLOUR`.) A first
+//    digit from `8` to `B` makes a colour bomb, with the colours laid out as for a plain
+//    half-hex, so `81` is a bomb with a blue inside and a green outline, (see `BOMB`.) A first
+//    digit from `C` to `F` makes a scramble half-hex in the same way, (see `SCRAMBLE`.)
+//
+// Whitespace between half-hexes is ignored, so rows can be indented and hexes spaced out to
+// taste. Lines that are empty, or that start with `;` are skipped.
+//
+// The result of `parse_level` can be passed straight to `GameState::from_grid`.
+use crate::game::{Grid, GridCell, HalfHexSpec};
+
+const ABSENT: char = '.';
+const ANIMATING: char = '~';
+const WALL: char = '#';
+const COMMENT: char = ';';
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LevelParseErrorKind {
+    UnexpectedChar(char),
+    UnfinishedHalfHex,
+    OddRowLength(usize),
+    WrongRowLength { expected: usize, found: usize },
+    TooManyHalfHexes(usize),
+    TooManyRows(usize),
+    NoRows,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelParseError {
+    // Both of these start from 1, to match what text editors show.
+    pub line: usize,
+    pub column: usize,
+    pub kind: LevelParseErrorKind,
+}
+
+impl std::fmt::Display for LevelParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use LevelParseErrorKind::*;
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match self.kind {
+            UnexpectedChar(c) => write!(f, "unexpected character {:?}", c),
+            UnfinishedHalfHex => write!(f, "half-hex is missing its second character"),
+            WrongRowLength { expected, found } => write!(
+                f,
+                "expected {} half-hexes in this row but found {}",
+                expected, found
+            ),
+            OddRowLength(found) => write!(
+                f,
+                "found {} half-hexes in this row, but every hex has two halves",
+                found
+            ),
+            TooManyHalfHexes(found) => write!(
+                f,
+                "found {} half-hexes in this row, but there can be at most {}",
+                found,
+                u8::MAX - 1
+            ),
+            TooManyRows(found) => write!(
+                f,
+                "found {} rows, but there can be at most {}",
+                found,
+                u8::MAX
+            ),
+            NoRows => write!(f, "there are no rows"),
+        }
+    }
+}
+
+impl std::error::Error for LevelParseError {}
+
+// On failure, returns the first character that doesn't fit.
+fn parse_cell(first: char, second: char) -> Result<GridCell<HalfHexSpec>, char> {
+    match (first, second) {
+        (ABSENT, ABSENT) => Ok(GridCell::Absent),
+        (ANIMATING, ANIMATING) => Ok(GridCell::Animating),
+        (WALL, WALL) => Ok(GridCell::Wall),
+        (ABSENT, _) | (ANIMATING, _) | (WALL, _) => Err(second),
+        _ => match (first.to_digit(16), second.to_digit(16)) {
+            (Some(low), Some(high)) => Ok(GridCell::Present((high << 4 | low) as HalfHexSpec)),
+            (None, _) => Err(first),
+            (Some(_), None) => Err(second),
+        },
+    }
+}
+
+pub fn parse_level(text: &str) -> Result<Grid, LevelParseError> {
+    let mut cells = Vec::new();
+    let mut width = None;
+    let mut row_count = 0;
+    let mut last_line = 0;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index + 1;
+        last_line = line_number;
+        macro_rules! err {
+            ($column: expr, $kind: expr) => {
+                return Err(LevelParseError {
+                    line: line_number,
+                    column: $column,
+                    kind: $kind,
+                })
+            };
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with(COMMENT) {
+            continue;
+        }
+
+        let row_start = cells.len();
+        let mut chars = line.chars().enumerate().filter(|(_, c)| !c.is_whitespace());
+        while let Some((first_index, first)) = chars.next() {
+            let (second_index, second) = match chars.next() {
+                Some(pair) => pair,
+                None => err!(first_index + 1, LevelParseErrorKind::UnfinishedHalfHex),
+            };
+
+            match parse_cell(first, second) {
+                Ok(cell) => cells.push(cell),
+                Err(c) => {
+                    let column = if c == first {
+                        first_index
+                    } else {
+                        second_index
+                    } + 1;
+                    err!(column, LevelParseErrorKind::UnexpectedChar(c))
+                }
+            }
+        }
+
+        // The first row decides how wide the grid is.
+        let found = cells.len() - row_start;
+        match width {
+            Some(expected) if found != expected => {
+                err!(1, LevelParseErrorKind::WrongRowLength { expected, found })
+            }
+            Some(_) => {}
+            None if found % 2 == 1 => err!(1, LevelParseErrorKind::OddRowLength(found)),
+            None if found >= u8::MAX as usize => {
+                err!(1, LevelParseErrorKind::TooManyHalfHexes(found))
+            }
+            None => width = Some(found),
+        }
+
+        row_count += 1;
+        if row_count > u8::MAX as usize {
+            err!(1, LevelParseErrorKind::TooManyRows(row_count));
+        }
+    }
+
+    let width = match width {
+        Some(width) => width,
+        None => {
+            return Err(LevelParseError {
+                line: last_line.max(1),
+                column: 1,
+                kind: LevelParseErrorKind::NoRows,
+            })
+        }
+    };
+
+    let mut grid = Grid::new(width as u8, row_count as u8);
+    grid.copy_from_slice(&cells);
+
+    Ok(grid)
+}
+
+pub fn serialize_level(grid: &Grid) -> String {
+    const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let width = grid.width() as usize;
+    // 2 characters per half-hex, a space per hex and a newline per row.
+    let mut output =
+        String::with_capacity(grid.len() * 2 + grid.len() / 2 + grid.height() as usize);
+
+    for row in grid.chunks(width) {
+        for (x, cell) in row.iter().enumerate() {
+            match *cell {
+                GridCell::Absent => {
+                    output.push(ABSENT);
+                    output.push(ABSENT);
+                }
+                GridCell::Animating => {
+                    output.push(ANIMATING);
+                    output.push(ANIMATING);
+                }
+                GridCell::Wall => {
+                    output.push(WALL);
+                    output.push(WALL);
+                }
+                GridCell::Present(spec) => {
+                    output.push(HEX_DIGITS[(spec & 0xF) as usize] as char);
+                    output.push(HEX_DIGITS[(spec >> 4) as usize] as char);
+                }
+            }
+
+            if x & 1 == 1 && x != width - 1 {
+                output.push(' ');
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::new_grid;
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn serialize_level_then_parse_level_round_trips_generated_grids() {
+        for seed in 0..8u8 {
+            let mut rng = XorShiftRng::from_seed([seed + 1; 16]);
+            let mut grid = new_grid(&mut rng);
+            grid[seed as usize] = GridCell::Animating;
+            grid[seed as usize + 2] = GridCell::Wall;
+
+            assert_eq!(parse_level(&serialize_level(&grid)), Ok(grid));
+        }
+    }
+
+    #[test]
+    fn parse_level_takes_its_size_from_the_text() {
+        let grid = parse_level("; a single hex, and a row below it\n0000 ....\n.... ....\n")
+            .expect("should parse");
+
+        assert_eq!((grid.width(), grid.height()), (4, 2));
+        assert_eq!(grid[0], GridCell::Present(0));
+        assert_eq!(
+            parse_level("0000\n000000\n").map_err(|e| e.kind),
+            Err(LevelParseErrorKind::WrongRowLength {
+                expected: 2,
+                found: 3
+            })
+        );
+    }
+
+    #[test]
+    fn parse_level_reports_where_the_bad_character_is() {
+        let width = 40;
+        let mut text = serialize_level(&Grid::new(width, 60));
+        let second_line_start = width as usize * 2 + width as usize / 2;
+        // the second character of the third half-hex on the second line.
+        text.replace_range(second_line_start + 6..second_line_start + 7, "x");
+
+        assert_eq!(
+            parse_level(&text),
+            Err(LevelParseError {
+                line: 2,
+                column: 7,
+                kind: LevelParseErrorKind::UnexpectedChar('x'),
+            })
+        );
+    }
+}
@@ -1,3 +1,59 @@
-
 mod game;
 pub use self::game::*;
+
+mod cursor_shape;
+pub use self::cursor_shape::*;
+
+mod hint;
+pub use self::hint::*;
+
+mod history;
+pub use self::history::*;
+
+mod matching;
+pub use self::matching::*;
+
+mod bombs;
+pub use self::bombs::*;
+
+mod scramble;
+pub use self::scramble::*;
+
+mod gravity;
+pub use self::gravity::*;
+
+mod refill;
+pub use self::refill::*;
+
+mod limits;
+pub use self::limits::*;
+
+mod rules;
+pub use self::rules::*;
+
+mod level;
+pub use self::level::*;
+
+mod solver;
+pub use self::solver::*;
+
+mod dead_board;
+pub use self::dead_board::*;
+
+mod generator;
+pub use self::generator::*;
+
+mod save;
+pub use self::save::*;
+
+mod scoring;
+pub use self::scoring::*;
+
+mod replay;
+pub use self::replay::*;
+
+mod ppm;
+pub use self::ppm::*;
+
+#[cfg(test)]
+mod golden;
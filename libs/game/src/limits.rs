@@ -0,0 +1,71 @@
+// Modes where the round can be lost, by running out of swaps, or out of time.
+//
+// The limit is part of the `RuleSet`, and `GameState` counts the swaps made and frames played
+// this round. Undoing a swap does not give it back.
+
+// The game runs at this many frames a second, which is only used to show the time left.
+pub const FRAMES_PER_SECOND: usize = 60;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Limit {
+    #[default]
+    None,
+    // This many swaps.
+    Moves(usize),
+    // This many frames.
+    Frames(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Ending {
+    Won,
+    OutOfMoves,
+    OutOfTime,
+}
+
+impl Limit {
+    pub fn allows_swap(self, swaps: usize) -> bool {
+        match self {
+            Limit::Moves(moves) => swaps < moves,
+            Limit::None | Limit::Frames(_) => true,
+        }
+    }
+
+    // How the round ends, if it does, this frame. Running out of moves only counts once
+    // everything has `settled`, since the last swap could still win.
+    pub(crate) fn ending(
+        self,
+        won: bool,
+        swaps: usize,
+        frames: usize,
+        settled: bool,
+    ) -> Option<Ending> {
+        match self {
+            _ if won => Some(Ending::Won),
+            Limit::Moves(moves) if swaps >= moves && settled => Some(Ending::OutOfMoves),
+            Limit::Frames(limit) if frames >= limit => Some(Ending::OutOfTime),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_run_out_once_things_settle_and_winning_comes_first() {
+        let moves = Limit::Moves(3);
+        assert!(moves.allows_swap(2) && !moves.allows_swap(3));
+        assert_eq!(moves.ending(false, 3, 1000, false), None);
+        assert_eq!(moves.ending(false, 3, 1000, true), Some(Ending::OutOfMoves));
+        assert_eq!(moves.ending(true, 3, 1000, true), Some(Ending::Won));
+
+        let frames = Limit::Frames(100);
+        assert!(frames.allows_swap(1000));
+        assert_eq!(frames.ending(false, 0, 99, false), None);
+        assert_eq!(frames.ending(false, 0, 100, false), Some(Ending::OutOfTime));
+
+        assert_eq!(Limit::None.ending(false, 1000, 1000, true), None);
+    }
+}
@@ -0,0 +1,108 @@
+// Deciding which hexes match, and clearing them. Everything that changes the grid, (landing a
+// swap, gravity, the solver,) goes through here, so halves that end up next to each other by
+// any route get cleared the same way.
+use crate::game::{colour_set, Grid, GridCell, HalfHexSpec};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClearedHex {
+    // The index of the left half. The right half is the one after it.
+    pub index: usize,
+    pub left: HalfHexSpec,
+    pub right: HalfHexSpec,
+}
+
+/// The match rule: the two halves of a hex match if they have the same outline colour, and
+/// share an inside colour. Plain half-hexes only have one inside colour, but multi-colour ones
+/// can have several.
+pub fn halves_match(left: HalfHexSpec, right: HalfHexSpec) -> bool {
+    let (left_insides, left_outline) = colour_set(left);
+    let (right_insides, right_outline) = colour_set(right);
+    left_outline == right_outline && left_insides & right_insides != 0
+}
+
+/// Clears every matching hex in the grid.
+pub fn resolve_matches(grid: &mut Grid) -> Vec<ClearedHex> {
+    let length = grid.len();
+    resolve_matches_at(grid, (0..length).step_by(2))
+}
+
+/// Clears the matching hexes among the ones that the given half-hexes are part of.
+pub fn resolve_matches_at<I>(grid: &mut Grid, affected: I) -> Vec<ClearedHex>
+where
+    I: IntoIterator<Item = usize>,
+{
+    let mut cleared = Vec::new();
+    for index in affected {
+        // Every row has an even number of half-hexes, so left halves are at even indexes.
+        let left_index = index & !1;
+        if let (GridCell::Present(left), GridCell::Present(right)) =
+            (grid[left_index], grid[left_index + 1])
+        {
+            if halves_match(left, right) {
+                grid[left_index] = GridCell::Absent;
+                grid[left_index + 1] = GridCell::Absent;
+                cleared.push(ClearedHex {
+                    index: left_index,
+                    left,
+                    right,
+                });
+            }
+        }
+    }
+
+    cleared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::MULTI_COLOUR;
+
+    #[test]
+    fn resolve_matches_only_clears_and_reports_matching_hexes() {
+        let mut grid = Grid::new(6, 2);
+        grid[0] = GridCell::Present(0x12);
+        grid[1] = GridCell::Present(0x21);
+        // Only the inside and outline colours count, not the unused bits.
+        grid[2] = GridCell::Present(0x13);
+        grid[3] = GridCell::Present(0xD3);
+        grid[4] = GridCell::Present(0x11);
+        grid[6] = GridCell::Present(0x22);
+        grid[7] = GridCell::Animating;
+
+        let before = grid.clone();
+        let cleared = resolve_matches(&mut grid);
+
+        assert_eq!(
+            cleared,
+            vec![ClearedHex {
+                index: 2,
+                left: 0x13,
+                right: 0xD3,
+            }]
+        );
+        for (i, (cell, old)) in grid.iter().zip(before.iter()).enumerate() {
+            if i == 2 || i == 3 {
+                assert!(cell.is_absent());
+            } else {
+                assert_eq!(cell, old);
+            }
+        }
+    }
+
+    #[test]
+    fn multi_colour_halves_match_on_any_shared_colour() {
+        // outline green, with blue and red insides.
+        let multi = MULTI_COLOUR | 0b0101_0000 | 0b01;
+        // outline green, with a red inside.
+        let plain = 0x12;
+
+        assert!(halves_match(multi, plain));
+        assert!(halves_match(plain, multi));
+        assert!(halves_match(multi, MULTI_COLOUR | 0b0100_0000 | 0b01));
+        // a yellow inside
+        assert!(!halves_match(multi, 0x13));
+        // a blue outline
+        assert!(!halves_match(multi, 0x02));
+    }
+}
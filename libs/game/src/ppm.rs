@@ -0,0 +1,53 @@
+// Frames as binary PPM files, which need no libraries to write or to look at. The headless
+// runner writes its screenshots with this, and the golden-image tests their references, so
+// the two always agree on the format.
+use platform_types::{SCREEN_HEIGHT, SCREEN_LENGTH, SCREEN_WIDTH};
+
+fn ppm_header() -> String {
+    format!("P6\n{} {}\n255\n", SCREEN_WIDTH, SCREEN_HEIGHT)
+}
+
+// The frame buffer holds one colour per pixel with red in the lowest byte, then green, then
+// blue, then alpha, which is thrown away here.
+pub fn to_ppm(frame_buffer: &[u32]) -> Vec<u8> {
+    let mut ppm = ppm_header().into_bytes();
+    ppm.reserve(frame_buffer.len() * 3);
+    for &colour in frame_buffer {
+        ppm.push(colour as u8);
+        ppm.push((colour >> 8) as u8);
+        ppm.push((colour >> 16) as u8);
+    }
+    ppm
+}
+
+// The reverse of `to_ppm`, for a whole screen's worth of pixels. Alpha comes back as 0xFF, which
+// is what it always is in the frame buffer.
+pub fn from_ppm(ppm: &[u8]) -> Option<Vec<u32>> {
+    let header = ppm_header();
+    if !ppm.starts_with(header.as_bytes()) || ppm.len() != header.len() + SCREEN_LENGTH * 3 {
+        return None;
+    }
+
+    let frame_buffer = ppm[header.len()..]
+        .chunks(3)
+        .map(|rgb| 0xFF00_0000 | (rgb[2] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[0] as u32)
+        .collect();
+
+    Some(frame_buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppms_are_red_then_green_then_blue() {
+        let ppm = to_ppm(&[0xFF33_52E1, 0xFF00_0000]);
+        assert!(ppm.starts_with(b"P6\n256 256\n255\n"));
+        assert_eq!(&ppm[15..], &[0xE1, 0x52, 0x33, 0, 0, 0]);
+
+        let frame_buffer: Vec<u32> = (0..SCREEN_LENGTH as u32).map(|i| 0xFF00_0000 | i).collect();
+        assert_eq!(from_ppm(&to_ppm(&frame_buffer)), Some(frame_buffer));
+        assert_eq!(from_ppm(&ppm), None);
+    }
+}
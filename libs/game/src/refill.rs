@@ -0,0 +1,117 @@
+// New half-hexes coming in from the edges of the grid, so it never runs out. They spawn in the
+// top or bottom row, then head straight for the middle until they run into something, (see
+// `Spawn`.)
+use crate::game::{Grid, GridCell, HalfHexSpec};
+use crate::gravity::translate;
+use crate::matching::halves_match;
+use rand::Rng;
+
+// More hexes come in than get cleared, so the grid fills up over time.
+pub const REFILL_HEXES_PER_PAIR: usize = 2;
+
+pub const DEFAULT_FILL_GOAL_PERCENT: u8 = 90;
+
+// A half-hex on its way from the edge of the grid at `from`, to `to`. The cell at `to` is
+// `Animating` until it gets there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Spawn {
+    pub from: usize,
+    pub to: usize,
+    pub spec: HalfHexSpec,
+}
+
+/// The percentage of the grid, other than walls, that has something in it, rounded down.
+pub fn fill_percent(grid: &Grid) -> u8 {
+    let walls = grid.iter().filter(|c| c.is_wall()).count();
+    let filled = grid
+        .iter()
+        .filter(|c| !c.is_absent() && !c.is_wall())
+        .count();
+    (filled * 100 / (grid.len() - walls).max(1)) as u8
+}
+
+// Spawns a whole hex, with halves that don't match each other, in an empty spot on the top or
+// bottom row. Returns `None` if there is no room on either of those rows.
+pub(crate) fn spawn_hex<R: Rng>(grid: &mut Grid, rng: &mut R) -> Option<[Spawn; 2]> {
+    let (width, height) = (grid.width() as usize, grid.height() as usize);
+    let last_row = (height - 1) * width;
+    // The left halves in the top row, then those in the bottom row.
+    let edge_slots: Vec<usize> = (0..width)
+        .step_by(2)
+        .chain((last_row..last_row + width).step_by(2))
+        .collect();
+
+    let start = rng.gen_range(0, edge_slots.len());
+    let from = (0..edge_slots.len())
+        .map(|i| edge_slots[(start + i) % edge_slots.len()])
+        .find(|&i| grid[i].is_absent() && grid[i + 1].is_absent())?;
+
+    let d_y = if from < last_row { 1 } else { -1 };
+    let mut to = from;
+    while let (Some(left), Some(right)) = (
+        translate(grid, to, (d_y, 0)),
+        translate(grid, to + 1, (d_y, 0)),
+    ) {
+        if !grid[left].is_absent() || !grid[right].is_absent() {
+            break;
+        }
+        to = left;
+    }
+
+    let left = random_plain_spec(rng);
+    let mut right = random_plain_spec(rng);
+    while halves_match(left, right) {
+        right = random_plain_spec(rng);
+    }
+
+    grid[to] = GridCell::Animating;
+    grid[to + 1] = GridCell::Animating;
+    Some([
+        Spawn {
+            from,
+            to,
+            spec: left,
+        },
+        Spawn {
+            from: from + 1,
+            to: to + 1,
+            spec: right,
+        },
+    ])
+}
+
+fn random_plain_spec<R: Rng>(rng: &mut R) -> HalfHexSpec {
+    rng.gen::<HalfHexSpec>() & 0b0011_0011
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn spawned_hexes_come_in_from_an_edge_and_stop_at_whatever_is_in_the_way() {
+        let mut grid = Grid::new(6, 7);
+        // A full middle row, so nothing can get past it.
+        for x in 0..6 {
+            let i = grid.xy_to_i(x, 3);
+            grid[i] = GridCell::Present(0x01);
+        }
+        let mut rng = XorShiftRng::from_seed([3; 16]);
+
+        for _ in 0..6 {
+            let [left, right] = spawn_hex(&mut grid, &mut rng).expect("there is room");
+            assert_eq!((left.from + 1, left.to + 1), (right.from, right.to));
+            assert!(!halves_match(left.spec, right.spec));
+
+            let (_, from_y) = grid.i_to_xy(left.from);
+            let (_, to_y) = grid.i_to_xy(left.to);
+            assert!(from_y == 0 || from_y == 6);
+            assert!(to_y != 3 && from_y.abs_diff(to_y) <= 2);
+            assert!(grid[left.to].is_animating() && grid[right.to].is_animating());
+        }
+
+        assert_eq!(fill_percent(&grid) as usize, (6 + 6 * 2) * 100 / 42);
+    }
+}
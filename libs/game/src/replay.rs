@@ -0,0 +1,330 @@
+// Recording a session and playing it back. Everything `EntireState` does follows from its seed
+// and the input it sees each frame, so that is all a replay needs to hold, along with the grid
+// the session ended on, to check the playback against.
+//
+// The text format is meant to be pasted into bug reports:
+//
+//     seed 000102030405060708090a0b0c0d0e0f
+//     input 12 00 00
+//     input 1 01 00
+//     grid
+//     <the final grid, in the level format from `level.rs`>
+//
+// Each `input` line is a number of frames in a row that all saw the same `Input`, followed by
+// its `gamepad` and `previous_gamepad` bits. `previous_gamepad` is needed too, since `press`
+// rewrites it to pass key repeats along. The counts can add up to at most `MAX_FRAMES`, so a
+// mistyped count can't ask for more memory than there is.
+use crate::game::{EntireState, Grid};
+use crate::level::{parse_level, serialize_level, LevelParseError};
+use platform_types::{Button, Input, State, StateParams, SFX};
+
+// Four hours at 60 frames a second, which is longer than anyone has played one session.
+pub const MAX_FRAMES: usize = 4 * 60 * 60 * 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replay {
+    pub seed: [u8; 16],
+    pub frames: Vec<Input>,
+    pub final_grid: Grid,
+}
+
+pub struct Recorder {
+    pub state: EntireState,
+    seed: [u8; 16],
+    frames: Vec<Input>,
+}
+
+impl Recorder {
+    pub fn new(params: StateParams) -> Self {
+        let seed = params.0;
+        Recorder {
+            state: EntireState::new(params),
+            seed,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn finish(self) -> Replay {
+        Replay {
+            seed: self.seed,
+            frames: self.frames,
+            final_grid: self.state.game_state.grid().clone(),
+        }
+    }
+}
+
+impl State for Recorder {
+    fn frame(&mut self, handle_sound: fn(SFX)) {
+        self.frames.push(self.state.input);
+        self.state.frame(handle_sound);
+    }
+
+    fn press(&mut self, button: Button::Ty) {
+        self.state.press(button);
+    }
+
+    fn release(&mut self, button: Button::Ty) {
+        self.state.release(button);
+    }
+
+    fn get_frame_buffer(&self) -> &[u32] {
+        self.state.get_frame_buffer()
+    }
+
+    fn save(&self) -> String {
+        self.state.save()
+    }
+
+    // A replay only has the seed to start from, so it can't start from a save.
+    fn load(&mut self, _saved: &str) -> Result<(), Box<dyn std::error::Error>> {
+        Err("a recording can't be loaded into, since replays start from a seed".into())
+    }
+}
+
+impl Replay {
+    /// Runs every recorded frame on a fresh state.
+    pub fn play(&self) -> EntireState {
+        let mut state = EntireState::from_seed(self.seed);
+        for &input in self.frames.iter() {
+            state.input = input;
+            state.frame(|_| {});
+        }
+
+        state
+    }
+
+    /// Panics, showing both grids, if playing the replay back does not end on the recorded grid.
+    pub fn assert_reproduces(&self) {
+        let state = self.play();
+        let grid = state.game_state.grid();
+        if *grid != self.final_grid {
+            panic!(
+                "replay diverged after {} frames.\nexpected:\n{}\nfound:\n{}",
+                self.frames.len(),
+                serialize_level(&self.final_grid),
+                serialize_level(grid)
+            );
+        }
+    }
+
+    pub fn serialize(&self) -> String {
+        let mut output = String::from("seed ");
+        for byte in self.seed.iter() {
+            output.push_str(&format!("{:02x}", byte));
+        }
+        output.push('\n');
+
+        let mut frames = self.frames.iter().peekable();
+        while let Some(input) = frames.next() {
+            let mut count = 1;
+            while frames.peek() == Some(&input) {
+                frames.next();
+                count += 1;
+            }
+
+            output.push_str(&format!(
+                "input {} {:02x} {:02x}\n",
+                count,
+                input.gamepad.bits(),
+                input.previous_gamepad.bits()
+            ));
+        }
+
+        output.push_str("grid\n");
+        output.push_str(&serialize_level(&self.final_grid));
+
+        output
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, ReplayParseError> {
+        let mut seed = None;
+        let mut frames = Vec::new();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line_number = line_index + 1;
+            let err = |kind| {
+                Err(ReplayParseError {
+                    line: line_number,
+                    kind,
+                })
+            };
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None => {}
+                Some("seed") => match words.next().and_then(parse_seed) {
+                    Some(s) => seed = Some(s),
+                    None => return err(ReplayParseErrorKind::BadSeed),
+                },
+                Some("input") => {
+                    let numbers: Vec<Option<usize>> = words
+                        .enumerate()
+                        .map(|(i, w)| usize::from_str_radix(w, if i == 0 { 10 } else { 16 }).ok())
+                        .collect();
+                    match numbers.as_slice() {
+                        [Some(count), Some(gamepad), Some(previous)]
+                            if *gamepad <= 0xFF
+                                && *previous <= 0xFF
+                                && *count <= MAX_FRAMES - frames.len() =>
+                        {
+                            let input = Input {
+                                gamepad: Button::Ty::from_bits_truncate(*gamepad as u8),
+                                previous_gamepad: Button::Ty::from_bits_truncate(*previous as u8),
+                            };
+                            frames.extend(std::iter::repeat_n(input, *count));
+                        }
+                        _ => return err(ReplayParseErrorKind::BadInput),
+                    }
+                }
+                Some("grid") => {
+                    let seed = match seed {
+                        Some(seed) => seed,
+                        None => return err(ReplayParseErrorKind::MissingSeed),
+                    };
+
+                    // Taking everything after this line, so the lines in level errors still
+                    // line up with the whole text.
+                    let rest: String = text
+                        .lines()
+                        .enumerate()
+                        .map(|(i, l)| if i > line_index { l } else { "" })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+
+                    return match parse_level(&rest) {
+                        Ok(final_grid) => Ok(Replay {
+                            seed,
+                            frames,
+                            final_grid,
+                        }),
+                        Err(e) => Err(ReplayParseError {
+                            line: e.line,
+                            kind: ReplayParseErrorKind::BadGrid(e),
+                        }),
+                    };
+                }
+                Some(_) => return err(ReplayParseErrorKind::UnknownLine),
+            }
+        }
+
+        Err(ReplayParseError {
+            line: text.lines().count(),
+            kind: ReplayParseErrorKind::MissingGrid,
+        })
+    }
+}
+
+// Seeds are written as 32 hex digits, here and in the headless runner's scripts.
+pub fn parse_seed(word: &str) -> Option<[u8; 16]> {
+    if word.len() != 32 || !word.is_ascii() {
+        return None;
+    }
+
+    let mut seed = [0; 16];
+    for (i, byte) in seed.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&word[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(seed)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayParseErrorKind {
+    BadSeed,
+    MissingSeed,
+    BadInput,
+    BadGrid(LevelParseError),
+    MissingGrid,
+    UnknownLine,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayParseError {
+    // Starts from 1, to match what text editors show.
+    pub line: usize,
+    pub kind: ReplayParseErrorKind,
+}
+
+impl std::fmt::Display for ReplayParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use ReplayParseErrorKind::*;
+        match self.kind {
+            BadSeed => write!(f, "line {}: the seed should be 32 hex digits", self.line),
+            MissingSeed => write!(f, "line {}: the seed must come before the grid", self.line),
+            BadInput => write!(
+                f,
+                "line {}: expected a frame count and two hex bytes, with at most {} frames in all",
+                self.line, MAX_FRAMES
+            ),
+            BadGrid(ref e) => write!(f, "{}", e),
+            MissingGrid => write!(f, "line {}: expected a grid at the end", self.line),
+            UnknownLine => write!(f, "line {}: expected `seed`, `input` or `grid`", self.line),
+        }
+    }
+}
+
+impl std::error::Error for ReplayParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_a_few_swaps(seed: [u8; 16]) -> Replay {
+        let mut recorder = Recorder::new((seed, None, None));
+        let script = [
+            Button::A,
+            Button::Right,
+            Button::A,
+            Button::Down,
+            Button::Down,
+            Button::A,
+            Button::Left,
+            Button::A,
+        ];
+        for &button in script.iter() {
+            recorder.press(button);
+            recorder.frame(|_| {});
+            recorder.release(button);
+            for _ in 0..30 {
+                recorder.frame(|_| {});
+            }
+        }
+
+        recorder.finish()
+    }
+
+    #[test]
+    fn replays_reproduce_the_recorded_session_after_a_round_trip_through_text() {
+        let replay = record_a_few_swaps([7; 16]);
+        let parsed = Replay::parse(&replay.serialize()).expect("should parse");
+
+        assert_eq!(parsed, replay);
+        parsed.assert_reproduces();
+    }
+
+    #[test]
+    #[should_panic(expected = "replay diverged")]
+    fn assert_reproduces_notices_a_different_seed() {
+        let mut replay = record_a_few_swaps([7; 16]);
+        replay.seed = [8; 16];
+
+        replay.assert_reproduces();
+    }
+
+    #[test]
+    fn frame_counts_past_the_limit_are_turned_away_before_anything_is_allocated() {
+        let seed = "seed 000102030405060708090a0b0c0d0e0f\n";
+        let error = Replay::parse(&format!("{}input 99999999999 00 00\ngrid\n", seed));
+        assert_eq!(
+            error.unwrap_err(),
+            ReplayParseError {
+                line: 2,
+                kind: ReplayParseErrorKind::BadInput
+            }
+        );
+
+        // It's the total that counts.
+        let text = format!("{}input {} 00 00\ninput 2 01 00\n", seed, MAX_FRAMES - 1);
+        assert_eq!(Replay::parse(&text).unwrap_err().line, 3);
+    }
+}
@@ -0,0 +1,340 @@
+// The rules of the game, split into mechanics that can each be switched on and off.
+//
+// A `Mechanic` gets called at a few points in each frame, (see the methods on `Mechanic`,) and
+// does whatever it does to the grid through a `Context`, which also collects what happened so
+// the game can play sounds and keep score. The solver runs the same hooks, so anything it
+// finds can be played out in the game.
+use crate::bombs::{bombs_in, set_off_bombs, Burst};
+use crate::game::{Grid, GridCell, HalfHexSpec};
+use crate::gravity::{fall, GravityMode, GravityPull};
+use crate::limits::Limit;
+use crate::matching::{resolve_matches, resolve_matches_at, ClearedHex};
+use crate::refill::{spawn_hex, Spawn, DEFAULT_FILL_GOAL_PERCENT, REFILL_HEXES_PER_PAIR};
+use crate::scramble::{scramble_around, DEFAULT_SCRAMBLE_RADIUS};
+use rand::prng::XorShiftRng;
+
+pub struct Context<'a> {
+    pub grid: &'a mut Grid,
+    pub rng: &'a mut XorShiftRng,
+    pub rules: &'a RuleSet,
+    pub outcome: Outcome,
+}
+
+// What happened during a call to `RuleSet::landed` or `RuleSet::step`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Outcome {
+    // The number of half-hexes that moved.
+    pub moved: usize,
+    pub cleared: Vec<ClearedHex>,
+    // New half-hexes, which need to be animated to where they are going.
+    pub spawned: Vec<Spawn>,
+    // Half-hexes that bombs set off, which need to be animated going off.
+    pub bursts: Vec<Burst>,
+    // The bombs that went off, so anything of their colours that is still moving can go too.
+    pub bombs: Vec<HalfHexSpec>,
+    // The indexes of every half-hex that a scramble shuffled.
+    pub scrambled: Vec<usize>,
+}
+
+impl Outcome {
+    pub fn changed_anything(&self) -> bool {
+        self.moved > 0
+            || !self.cleared.is_empty()
+            || !self.spawned.is_empty()
+            || !self.bursts.is_empty()
+            || !self.scrambled.is_empty()
+    }
+
+    // Half-hexes that bombs set off are worth half a pair each.
+    pub fn pairs_cleared(&self) -> usize {
+        self.cleared.len() + self.bursts.len() / 2
+    }
+
+    /// Does what would otherwise be animated straight away, for when nothing is animating.
+    pub fn finish_instantly(&self, grid: &mut Grid) {
+        for spawn in self.spawned.iter() {
+            grid[spawn.to] = GridCell::Present(spawn.spec);
+        }
+        for burst in self.bursts.iter() {
+            grid[burst.index] = GridCell::Absent;
+        }
+    }
+}
+
+pub trait Mechanic {
+    /// Called when half-hexes land at the end of a swap, with the indexes they landed at.
+    fn landed(&self, _context: &mut Context, _indexes: &[usize]) {}
+
+    /// Called once a frame, after any landings.
+    fn step(&self, _context: &mut Context) {}
+
+    /// Called with the hexes that the hooks above, or this one, cleared. Anything this clears
+    /// gets passed to every mechanic's `cleared` in turn as well.
+    fn cleared(&self, _context: &mut Context, _cleared: &[ClearedHex]) {}
+}
+
+// The order here is the order the mechanics run in. Matching comes first so a swap can clear
+// what it lands on before anything falls into or out of place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum MechanicKind {
+    Matching,
+    Bombs,
+    Scramble,
+    Gravity,
+    Refill,
+}
+
+use MechanicKind::*;
+
+pub const MECHANIC_KINDS: [MechanicKind; 5] = [Matching, Bombs, Scramble, Gravity, Refill];
+
+impl MechanicKind {
+    pub fn mechanic(self) -> &'static dyn Mechanic {
+        match self {
+            Matching => &MatchingMechanic,
+            Bombs => &BombsMechanic,
+            Scramble => &ScrambleMechanic,
+            Gravity => &GravityMechanic,
+            Refill => &RefillMechanic,
+        }
+    }
+
+    // `Refill` changes what the goal is, so it is a mode you pick rather than part of the usual
+    // game.
+    fn is_on_by_default(self) -> bool {
+        self != Refill
+    }
+}
+
+struct GravityMechanic;
+
+impl GravityMechanic {
+    fn fall(&self, context: &mut Context) {
+        let rules = context.rules;
+        context.outcome.moved += fall(context.grid, rules.gravity_mode, rules.gravity_pull);
+    }
+}
+
+impl Mechanic for GravityMechanic {
+    fn landed(&self, context: &mut Context, _indexes: &[usize]) {
+        self.fall(context);
+    }
+
+    fn step(&self, context: &mut Context) {
+        self.fall(context);
+    }
+}
+
+struct MatchingMechanic;
+
+impl Mechanic for MatchingMechanic {
+    fn landed(&self, context: &mut Context, indexes: &[usize]) {
+        let cleared = resolve_matches_at(context.grid, indexes.iter().cloned());
+        context.outcome.cleared.extend(cleared);
+    }
+
+    // This clears whatever gravity brought together on the step before.
+    fn step(&self, context: &mut Context) {
+        let cleared = resolve_matches(context.grid);
+        context.outcome.cleared.extend(cleared);
+    }
+}
+
+struct BombsMechanic;
+
+impl Mechanic for BombsMechanic {
+    fn cleared(&self, context: &mut Context, cleared: &[ClearedHex]) {
+        let bursts = set_off_bombs(context.grid, cleared);
+        context.outcome.bursts.extend(bursts);
+        context
+            .outcome
+            .bombs
+            .extend(bombs_in(cleared).map(|(_, bomb)| bomb));
+    }
+}
+
+struct ScrambleMechanic;
+
+impl Mechanic for ScrambleMechanic {
+    fn cleared(&self, context: &mut Context, cleared: &[ClearedHex]) {
+        let radius = context.rules.scramble_radius;
+        let scrambled = scramble_around(context.grid, context.rng, cleared, radius);
+        context.outcome.scrambled.extend(scrambled);
+    }
+}
+
+// Each cleared pair brings in new hexes from the edges, (see `refill.rs`.)
+struct RefillMechanic;
+
+impl Mechanic for RefillMechanic {
+    fn cleared(&self, context: &mut Context, cleared: &[ClearedHex]) {
+        for _ in 0..cleared.len() * REFILL_HEXES_PER_PAIR {
+            if let Some(spawned) = spawn_hex(context.grid, context.rng) {
+                context.outcome.spawned.extend(spawned.iter());
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RuleSet {
+    enabled: [bool; MECHANIC_KINDS.len()],
+    pub gravity_mode: GravityMode,
+    pub gravity_pull: GravityPull,
+    // With `Refill` on, the grid never empties, so the goal is to get it this full instead.
+    pub fill_goal_percent: u8,
+    // How many steps out from a matched scramble half-hex get shuffled.
+    pub scramble_radius: usize,
+    // A number of swaps, or of frames, to win in.
+    pub limit: Limit,
+}
+
+impl Default for RuleSet {
+    /// Every mechanic except `Refill` is on.
+    fn default() -> Self {
+        RuleSet {
+            enabled: MECHANIC_KINDS.map(MechanicKind::is_on_by_default),
+            gravity_mode: GravityMode::default(),
+            gravity_pull: GravityPull::default(),
+            fill_goal_percent: DEFAULT_FILL_GOAL_PERCENT,
+            scramble_radius: DEFAULT_SCRAMBLE_RADIUS,
+            limit: Limit::default(),
+        }
+    }
+}
+
+impl RuleSet {
+    pub fn is_enabled(&self, kind: MechanicKind) -> bool {
+        self.enabled[kind as usize]
+    }
+
+    pub fn set_enabled(&mut self, kind: MechanicKind, enabled: bool) {
+        self.enabled[kind as usize] = enabled;
+    }
+
+    pub fn with(mut self, kind: MechanicKind, enabled: bool) -> Self {
+        self.set_enabled(kind, enabled);
+        self
+    }
+
+    fn mechanics(&self) -> impl Iterator<Item = &'static dyn Mechanic> + '_ {
+        MECHANIC_KINDS
+            .iter()
+            .filter(move |&&kind| self.is_enabled(kind))
+            .map(|kind| kind.mechanic())
+    }
+
+    pub fn landed(&self, grid: &mut Grid, rng: &mut XorShiftRng, indexes: &[usize]) -> Outcome {
+        let mut context = self.context(grid, rng);
+        for mechanic in self.mechanics() {
+            mechanic.landed(&mut context, indexes);
+        }
+        self.pass_along_clears(&mut context);
+
+        context.outcome
+    }
+
+    pub fn step(&self, grid: &mut Grid, rng: &mut XorShiftRng) -> Outcome {
+        let mut context = self.context(grid, rng);
+        for mechanic in self.mechanics() {
+            mechanic.step(&mut context);
+        }
+        self.pass_along_clears(&mut context);
+
+        context.outcome
+    }
+
+    /// Steps until nothing changes any more, finishing anything that would be animated
+    /// straight away.
+    pub fn settle(&self, grid: &mut Grid, rng: &mut XorShiftRng) {
+        // If something is still changing after this many steps then it's never going to stop.
+        for _ in 0..grid.len() {
+            let outcome = self.step(grid, rng);
+            outcome.finish_instantly(grid);
+            if !outcome.changed_anything() {
+                break;
+            }
+        }
+    }
+
+    fn context<'a>(&'a self, grid: &'a mut Grid, rng: &'a mut XorShiftRng) -> Context<'a> {
+        Context {
+            grid,
+            rng,
+            rules: self,
+            outcome: Outcome::default(),
+        }
+    }
+
+    // Clearing can cause more clearing, so this keeps going until nothing new is cleared.
+    fn pass_along_clears(&self, context: &mut Context) {
+        let mut handled = 0;
+        while handled < context.outcome.cleared.len() {
+            let new_clears = context.outcome.cleared[handled..].to_vec();
+            handled = context.outcome.cleared.len();
+            for mechanic in self.mechanics() {
+                mechanic.cleared(context, &new_clears);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GridCell, GRID_HEIGHT, GRID_WIDTH};
+    use rand::SeedableRng;
+
+    #[test]
+    fn switched_off_mechanics_do_nothing() {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        // Far from the middle, so it would fall if gravity were on.
+        let corner = grid.xy_to_i(4, 4);
+        grid[corner] = GridCell::Present(0x11);
+        grid[corner + 1] = GridCell::Present(0x11);
+        let mut rng = XorShiftRng::from_seed([1; 16]);
+
+        let mut still = grid.clone();
+        let rules = RuleSet::default()
+            .with(Matching, false)
+            .with(Gravity, false);
+        rules.settle(&mut still, &mut rng);
+        assert_eq!(still, grid);
+
+        let mut fallen = grid.clone();
+        let outcome = RuleSet::default()
+            .with(Matching, false)
+            .step(&mut fallen, &mut rng);
+        assert!(outcome.moved > 0);
+        assert_eq!(fallen.iter().filter(|c| c.is_present()).count(), 2);
+
+        let mut cleared = grid.clone();
+        let outcome = RuleSet::default()
+            .with(Gravity, false)
+            .step(&mut cleared, &mut rng);
+        assert_eq!((outcome.moved, outcome.cleared.len()), (0, 1));
+        assert!(cleared.iter().all(|c| c.is_absent()));
+    }
+
+    #[test]
+    fn refill_brings_in_more_half_hexes_than_it_cleared() {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        let middle = grid.xy_to_i(GRID_WIDTH / 2, GRID_HEIGHT / 2);
+        grid[middle] = GridCell::Present(0x11);
+        grid[middle + 1] = GridCell::Present(0x11);
+        let mut rng = XorShiftRng::from_seed([1; 16]);
+
+        let outcome = RuleSet::default()
+            .with(Refill, true)
+            .step(&mut grid, &mut rng);
+        assert_eq!(outcome.cleared.len(), 1);
+        assert_eq!(outcome.spawned.len(), REFILL_HEXES_PER_PAIR * 2);
+        assert!(outcome.spawned.iter().all(|s| grid[s.to].is_animating()));
+
+        outcome.finish_instantly(&mut grid);
+        assert_eq!(
+            grid.iter().filter(|c| c.is_present()).count(),
+            REFILL_HEXES_PER_PAIR * 2
+        );
+    }
+}
@@ -0,0 +1,709 @@
+// Saving a whole `GameState`, so it can be picked back up exactly where it was, with anything
+// in flight still in flight, and the same random numbers still to come.
+//
+// The format is text, one thing per line, starting with a keyword, in a fixed order:
+//
+//     save 1
+//     rng 000102030405060708090a0b0c0d0e0f
+//     frames 1234 1200
+//     swaps 3
+//     ...
+//     grid 60
+//     <the grid, in the level format from `level.rs`, one line per row>
+//
+// The number after `save` is the version of the format. A save with any other version is
+// turned away rather than loaded wrong, so it needs bumping whenever the format changes.
+// Anything that gets worked out again every frame, like whether the player is stuck, is left
+// out.
+use crate::cursor_shape::CursorShape;
+use crate::game::{fits_on_screen, Animation, Cursor, Dir, Fuse, GameState, Grid, HalfHexSpec};
+use crate::gravity::{GravityMode, GravityPull};
+use crate::history::History;
+use crate::level::{parse_level, serialize_level, LevelParseError};
+use crate::limits::{Ending, Limit};
+use crate::replay::parse_seed;
+use crate::rules::{RuleSet, MECHANIC_KINDS};
+use crate::scoring::Score;
+use rand::prng::XorShiftRng;
+use rand::{RngCore, SeedableRng};
+use std::fmt::Write;
+use std::str::FromStr;
+
+pub const SAVE_VERSION: u32 = 1;
+
+// `XorShiftRng` keeps its state to itself, but its state is always the last four numbers it
+// gave out. So this takes four from a copy, then works back to the state the original is in,
+// in the form `from_seed` takes.
+fn rng_seed(rng: &XorShiftRng) -> [u8; 16] {
+    let mut copy = rng.clone();
+    let mut state = [0u32; 4];
+    for number in state.iter_mut() {
+        *number = copy.next_u32();
+    }
+    for _ in 0..state.len() {
+        state = step_back(state);
+    }
+
+    let mut seed = [0; 16];
+    for (bytes, number) in seed.chunks_mut(4).zip(state.iter()) {
+        bytes.copy_from_slice(&number.to_le_bytes());
+    }
+    seed
+}
+
+// Undoes one `next_u32`, which moves each number in the state down one place, then puts
+// `w ^ (w >> 19) ^ t ^ (t >> 8)` at the end, where `t` is `x ^ (x << 11)`.
+fn step_back([y, z, w, new_w]: [u32; 4]) -> [u32; 4] {
+    let t = new_w ^ w ^ (w >> 19);
+    let t = t ^ (t >> 8) ^ (t >> 16) ^ (t >> 24);
+    let x = t ^ (t << 11) ^ (t << 22);
+    [x, y, z, w]
+}
+
+fn dir_char(dir: Dir) -> char {
+    match dir {
+        Dir::Up => 'U',
+        Dir::Down => 'D',
+        Dir::Left => 'L',
+        Dir::Right => 'R',
+    }
+}
+
+fn char_dir(c: char) -> Option<Dir> {
+    match c {
+        'U' => Some(Dir::Up),
+        'D' => Some(Dir::Down),
+        'L' => Some(Dir::Left),
+        'R' => Some(Dir::Right),
+        _ => None,
+    }
+}
+
+impl GameState {
+    pub fn save(&self) -> String {
+        let mut output = String::new();
+        // Writing to a `String` can't fail.
+        macro_rules! line {
+            ($($arg: tt)*) => {
+                writeln!(output, $($arg)*).unwrap()
+            };
+        }
+
+        line!("save {}", SAVE_VERSION);
+        let seed: String = rng_seed(&self.rng)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        line!("rng {}", seed);
+        line!("frames {} {}", self.frame_counter, self.frames_played);
+        line!("swaps {}", self.swap_count);
+        line!(
+            "score {} {} {}",
+            self.score.points,
+            self.score.combo,
+            self.score.cascade
+        );
+        match self.cursor {
+            Cursor::Unselected(c) => line!("cursor {}", c),
+            Cursor::Selected(c1, c2) => line!("cursor {} {}", c1, c2),
+        }
+        let paths: Vec<String> = self
+            .cursor_shape
+            .paths()
+            .iter()
+            .map(|path| path.iter().cloned().map(dir_char).collect())
+            .collect();
+        line!("shape {}", paths.join(" "));
+
+        let enabled: Vec<String> = MECHANIC_KINDS
+            .iter()
+            .filter(|&&kind| self.rules.is_enabled(kind))
+            .map(|kind| format!("{:?}", kind))
+            .collect();
+        line!("mechanics {}", enabled.join(" "));
+        line!(
+            "gravity {:?} {:?}",
+            self.rules.gravity_mode,
+            self.rules.gravity_pull
+        );
+        line!("fill_goal {}", self.rules.fill_goal_percent);
+        line!("scramble_radius {}", self.rules.scramble_radius);
+        line!("limit {:?}", self.rules.limit);
+        line!("ending {:?}", self.ending);
+
+        let scrambled: Vec<String> = self.scrambled.iter().map(|i| i.to_string()).collect();
+        line!(
+            "scrambled {} {}",
+            self.scramble_frames_left,
+            scrambled.join(" ")
+        );
+        line!("fuses {}", self.fuses.len());
+        for fuse in self.fuses.iter() {
+            line!("fuse {} {:02x} {}", fuse.index, fuse.spec, fuse.frames_left);
+        }
+        line!("animations {}", self.animations.len());
+        for a in self.animations.iter() {
+            line!(
+                "animation {} {} {} {} {} {} {} {} {}",
+                a.x,
+                a.y,
+                a.target_x,
+                a.target_y,
+                a.x_rate,
+                a.y_rate,
+                a.spec
+                    .map_or("-".to_string(), |spec| format!("{:02x}", spec)),
+                a.swap.map_or("-".to_string(), |swap| swap.to_string()),
+                a.swap_cleared as u8
+            );
+        }
+
+        line!("grid {}", self.grid.height());
+        output.push_str(&serialize_level(&self.grid));
+        line!("start {} {}", self.start_cursor, self.start_grid.height());
+        output.push_str(&serialize_level(&self.start_grid));
+
+        let (undo, redo) = self.history.snapshots();
+        for &(name, snapshots) in [("undo", undo), ("redo", redo)].iter() {
+            line!("{} {}", name, snapshots.len());
+            for (grid, score) in snapshots.iter() {
+                line!(
+                    "snapshot {} {} {} {}",
+                    score.points,
+                    score.combo,
+                    score.cascade,
+                    grid.height()
+                );
+                output.push_str(&serialize_level(grid));
+            }
+        }
+
+        output
+    }
+
+    /// Restores what `save` returned.
+    pub fn load(text: &str) -> Result<GameState, SaveParseError> {
+        let mut reader = Reader {
+            lines: text.lines().collect(),
+            next: 0,
+        };
+
+        let version: u32 = reader.value("save")?;
+        if version != SAVE_VERSION {
+            return reader.err(SaveParseErrorKind::UnknownVersion(version));
+        }
+
+        let seed = reader.parsed("rng", |words| match *words {
+            [word] => parse_seed(word),
+            _ => None,
+        })?;
+        let rng = XorShiftRng::from_seed(seed);
+
+        let (frame_counter, frames_played) = reader.pair("frames")?;
+        let swap_count = reader.value("swaps")?;
+        let score = reader.parsed("score", |words| match *words {
+            [points, combo, cascade] => Some(Score {
+                points: parse(points)?,
+                combo: parse(combo)?,
+                cascade: parse(cascade)?,
+            }),
+            _ => None,
+        })?;
+
+        let cursor = reader.parsed("cursor", |words| match *words {
+            [c] => Some(Cursor::Unselected(parse(c)?)),
+            [c1, c2] => Some(Cursor::Selected(parse(c1)?, parse(c2)?)),
+            _ => None,
+        })?;
+        let cursor_shape = reader.parsed("shape", |words| {
+            let paths: Option<Vec<Vec<Dir>>> = words
+                .iter()
+                .map(|word| word.chars().map(char_dir).collect())
+                .collect();
+            paths.map(CursorShape::new)
+        })?;
+
+        let mut rules = RuleSet::default();
+        let enabled = reader.parsed("mechanics", |words| {
+            words
+                .iter()
+                .map(|&word| {
+                    MECHANIC_KINDS
+                        .iter()
+                        .find(|kind| format!("{:?}", kind) == word)
+                        .cloned()
+                })
+                .collect::<Option<Vec<_>>>()
+        })?;
+        for &kind in MECHANIC_KINDS.iter() {
+            rules.set_enabled(kind, enabled.contains(&kind));
+        }
+        let (gravity_mode, gravity_pull) = reader.parsed("gravity", |words| match *words {
+            [mode, pull] => parse_gravity_mode(mode).zip(parse_gravity_pull(pull)),
+            _ => None,
+        })?;
+        rules.gravity_mode = gravity_mode;
+        rules.gravity_pull = gravity_pull;
+        rules.fill_goal_percent = reader.value("fill_goal")?;
+        rules.scramble_radius = reader.value("scramble_radius")?;
+        rules.limit = reader.parsed("limit", |words| match *words {
+            [word] => parse_limit(word),
+            _ => None,
+        })?;
+        let ending = reader.parsed("ending", |words| match *words {
+            [word] => parse_ending(word),
+            _ => None,
+        })?;
+
+        let (scramble_frames_left, scrambled) =
+            reader.parsed("scrambled", |words| match words.split_first() {
+                Some((frames_left, scrambled)) => Some((
+                    parse(frames_left)?,
+                    scrambled
+                        .iter()
+                        .map(|word| parse(word))
+                        .collect::<Option<Vec<usize>>>()?,
+                )),
+                None => None,
+            })?;
+
+        let fuse_count: usize = reader.value("fuses")?;
+        let mut fuses = Vec::with_capacity(fuse_count);
+        for _ in 0..fuse_count {
+            fuses.push(reader.parsed("fuse", |words| match *words {
+                [index, spec, frames_left] => Some(Fuse {
+                    index: parse(index)?,
+                    spec: parse_spec(spec)?,
+                    frames_left: parse(frames_left)?,
+                }),
+                _ => None,
+            })?);
+        }
+
+        let animation_count: usize = reader.value("animations")?;
+        let mut animations = Vec::with_capacity(animation_count);
+        // Which line each one is on, for if it turns out to be off the grid.
+        let mut animation_lines = Vec::with_capacity(animation_count);
+        for _ in 0..animation_count {
+            animations.push(reader.parsed("animation", |words| match *words {
+                [x, y, target_x, target_y, x_rate, y_rate, spec, swap, swap_cleared] => {
+                    Some(Animation {
+                        x: parse(x)?,
+                        y: parse(y)?,
+                        target_x: parse(target_x)?,
+                        target_y: parse(target_y)?,
+                        x_rate: parse(x_rate)?,
+                        y_rate: parse(y_rate)?,
+                        spec: optional(spec, parse_spec)?,
+                        swap: optional(swap, parse)?,
+                        swap_cleared: parse::<u8>(swap_cleared)? == 1,
+                    })
+                }
+                _ => None,
+            })?);
+            animation_lines.push(reader.next);
+        }
+
+        let rows = reader.value("grid")?;
+        let grid = reader.grid(rows, None)?;
+        // Undoing, or starting again, swaps these in, and the cursor and animations have to
+        // stay on the grid when that happens.
+        let size = Some((grid.width(), grid.height()));
+        let (start_cursor, rows) = reader.pair("start")?;
+        let start_grid = reader.grid(rows, size)?;
+
+        let mut snapshots = Vec::with_capacity(2);
+        for &name in ["undo", "redo"].iter() {
+            let count: usize = reader.value(name)?;
+            let mut list = Vec::with_capacity(count);
+            for _ in 0..count {
+                let (score, rows) = reader.parsed("snapshot", |words| match *words {
+                    [points, combo, cascade, rows] => Some((
+                        Score {
+                            points: parse(points)?,
+                            combo: parse(combo)?,
+                            cascade: parse(cascade)?,
+                        },
+                        parse(rows)?,
+                    )),
+                    _ => None,
+                })?;
+                list.push((reader.grid(rows, size)?, score));
+            }
+            snapshots.push(list);
+        }
+        let redo = snapshots.pop().unwrap_or_default();
+        let undo = snapshots.pop().unwrap_or_default();
+
+        // Anything pointing outside the grid would only cause a panic later on.
+        let anchors = match cursor {
+            Cursor::Unselected(c) => vec![c],
+            Cursor::Selected(c1, c2) => vec![c1, c2],
+        };
+        let out_of_bounds = anchors
+            .into_iter()
+            .chain(scrambled.iter().cloned())
+            .chain(fuses.iter().map(|fuse| fuse.index))
+            .find(|&i| i >= grid.len())
+            .or_else(|| Some(start_cursor).filter(|&c| c >= start_grid.len()));
+        if let Some(i) = out_of_bounds {
+            return reader.err(SaveParseErrorKind::OutOfBounds(i));
+        }
+        // An index made from an `x` that is too big can still be inside the grid, so these
+        // are reported as they were written.
+        let off_grid = animations
+            .iter()
+            .zip(animation_lines)
+            .flat_map(|(a, line)| [(a.x, a.y, line), (a.target_x, a.target_y, line)])
+            .find(|&(x, y, _)| x >= grid.width() || y >= grid.height());
+        if let Some((x, y, line)) = off_grid {
+            return Err(SaveParseError {
+                line,
+                kind: SaveParseErrorKind::PositionOutOfBounds(x, y),
+            });
+        }
+
+        Ok(GameState {
+            grid,
+            cursor,
+            cursor_shape,
+            frame_counter,
+            frames_played,
+            ending,
+            stuck: None,
+            start_grid,
+            start_cursor,
+            animations,
+            fuses,
+            scrambled,
+            scramble_frames_left,
+            history: History::from_snapshots(undo, redo),
+            score,
+            swap_count,
+            rules,
+            rng,
+        })
+    }
+}
+
+fn parse<T: FromStr>(word: &str) -> Option<T> {
+    word.parse().ok()
+}
+
+fn parse_spec(word: &str) -> Option<HalfHexSpec> {
+    HalfHexSpec::from_str_radix(word, 16).ok()
+}
+
+// `-` is `None`, and anything else has to be a `T`.
+fn optional<T>(word: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Option<T>> {
+    if word == "-" {
+        Some(None)
+    } else {
+        parse(word).map(Some)
+    }
+}
+
+// The `x` in `name(x)`, as `{:?}` writes it.
+fn inside<'a>(word: &'a str, name: &str) -> Option<&'a str> {
+    word.strip_prefix(name)?
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+fn parse_gravity_mode(word: &str) -> Option<GravityMode> {
+    match word {
+        "PerCell" => Some(GravityMode::PerCell),
+        "RigidChunks" => Some(GravityMode::RigidChunks),
+        _ => None,
+    }
+}
+
+fn parse_gravity_pull(word: &str) -> Option<GravityPull> {
+    match word {
+        "Center" => Some(GravityPull::Center),
+        "Down" => Some(GravityPull::Down),
+        "Off" => Some(GravityPull::Off),
+        _ => parse(inside(word, "Attractor")?).map(GravityPull::Attractor),
+    }
+}
+
+fn parse_limit(word: &str) -> Option<Limit> {
+    match word {
+        "None" => Some(Limit::None),
+        _ => match inside(word, "Moves") {
+            Some(moves) => parse(moves).map(Limit::Moves),
+            None => parse(inside(word, "Frames")?).map(Limit::Frames),
+        },
+    }
+}
+
+fn parse_ending(word: &str) -> Option<Option<Ending>> {
+    match word {
+        "None" => Some(None),
+        _ => match inside(word, "Some")? {
+            "Won" => Some(Some(Ending::Won)),
+            "OutOfMoves" => Some(Some(Ending::OutOfMoves)),
+            "OutOfTime" => Some(Some(Ending::OutOfTime)),
+            _ => None,
+        },
+    }
+}
+
+struct Reader<'a> {
+    lines: Vec<&'a str>,
+    next: usize,
+}
+
+impl<'a> Reader<'a> {
+    // The line that was read last, starting from 1 to match what text editors show.
+    fn err<T>(&self, kind: SaveParseErrorKind) -> Result<T, SaveParseError> {
+        Err(SaveParseError {
+            line: self.next.max(1),
+            kind,
+        })
+    }
+
+    // The words after `keyword`, which the next line has to start with.
+    fn line(&mut self, keyword: &'static str) -> Result<Vec<&'a str>, SaveParseError> {
+        let line = self.lines.get(self.next).cloned().unwrap_or("");
+        self.next += 1;
+
+        let mut words = line.split_whitespace();
+        if words.next() != Some(keyword) {
+            return self.err(SaveParseErrorKind::Expected(keyword));
+        }
+        Ok(words.collect())
+    }
+
+    // The next line, made into a `T` by `parse_words`.
+    fn parsed<T>(
+        &mut self,
+        keyword: &'static str,
+        parse_words: impl FnOnce(&[&'a str]) -> Option<T>,
+    ) -> Result<T, SaveParseError> {
+        let words = self.line(keyword)?;
+        match parse_words(&words) {
+            Some(parsed) => Ok(parsed),
+            None => self.err(SaveParseErrorKind::BadValue),
+        }
+    }
+
+    fn value<T: FromStr>(&mut self, keyword: &'static str) -> Result<T, SaveParseError> {
+        self.parsed(keyword, |words| match *words {
+            [word] => parse(word),
+            _ => None,
+        })
+    }
+
+    fn pair(&mut self, keyword: &'static str) -> Result<(usize, usize), SaveParseError> {
+        self.parsed(keyword, |words| match *words {
+            [first, second] => Some((parse(first)?, parse(second)?)),
+            _ => None,
+        })
+    }
+
+    // The next `rows` lines, as a grid that fits on the screen, and is `size`, if that is given.
+    fn grid(&mut self, rows: usize, size: Option<(u8, u8)>) -> Result<Grid, SaveParseError> {
+        let start = self.next;
+        let end = start + rows;
+        if rows == 0 || end > self.lines.len() {
+            self.next = self.lines.len();
+            return self.err(SaveParseErrorKind::Expected("more rows of the grid"));
+        }
+        self.next = end;
+
+        let grid = parse_level(&self.lines[start..end].join("\n")).map_err(|e| SaveParseError {
+            line: start + e.line,
+            kind: SaveParseErrorKind::BadGrid(e),
+        })?;
+
+        let found = (grid.width(), grid.height());
+        let kind = if !fits_on_screen(found.0, found.1) {
+            SaveParseErrorKind::TooBigForScreen(found.0, found.1)
+        } else if size.is_some_and(|size| size != found) {
+            SaveParseErrorKind::DifferentSize(found.0, found.1)
+        } else {
+            return Ok(grid);
+        };
+        // The line before the rows, which says how many there are.
+        Err(SaveParseError { line: start, kind })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SaveParseErrorKind {
+    UnknownVersion(u32),
+    Expected(&'static str),
+    BadValue,
+    BadGrid(LevelParseError),
+    OutOfBounds(usize),
+    PositionOutOfBounds(u8, u8),
+    TooBigForScreen(u8, u8),
+    // A grid that isn't the same width and height as the current one.
+    DifferentSize(u8, u8),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SaveParseError {
+    // Starts from 1, to match what text editors show.
+    pub line: usize,
+    pub kind: SaveParseErrorKind,
+}
+
+impl std::fmt::Display for SaveParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        use SaveParseErrorKind::*;
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            UnknownVersion(version) => write!(
+                f,
+                "this is a version {} save, but only version {} can be loaded",
+                version, SAVE_VERSION
+            ),
+            Expected(what) => write!(f, "expected {}", what),
+            BadValue => write!(f, "a value on this line is missing or not understood"),
+            BadGrid(ref e) => write!(f, "{}", e),
+            OutOfBounds(i) => write!(f, "{} is outside the grid", i),
+            PositionOutOfBounds(x, y) => write!(f, "({}, {}) is outside the grid", x, y),
+            TooBigForScreen(width, height) => {
+                write!(f, "a {}x{} grid does not fit on the screen", width, height)
+            }
+            DifferentSize(width, height) => write!(
+                f,
+                "this {}x{} grid is not the same size as the current one",
+                width, height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SaveParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::update_and_render;
+    use platform_types::{Button, Input, Speaker};
+    use rand::Rng;
+    use rendering::Framebuffer;
+
+    fn frame_with(state: &mut GameState, gamepad: Button::Ty) {
+        let input = Input {
+            gamepad,
+            previous_gamepad: Button::Ty::empty(),
+        };
+        update_and_render(&mut Framebuffer::new(), state, input, &mut Speaker::new());
+    }
+
+    #[test]
+    fn the_rng_state_can_be_worked_out_from_what_comes_next() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        for _ in 0..37 {
+            rng.next_u32();
+        }
+
+        let mut restored = XorShiftRng::from_seed(rng_seed(&rng));
+        for _ in 0..16 {
+            assert_eq!(restored.gen::<u64>(), rng.gen::<u64>());
+        }
+    }
+
+    #[test]
+    fn a_game_loaded_mid_swap_plays_out_exactly_like_the_original() {
+        let mut state = GameState::new([3; 16], RuleSet::default());
+        // From the empty edge to somewhere full, and across it a way.
+        let moves = [Button::Down; 4].iter().chain([Button::Right; 4].iter());
+        let swap = [
+            Button::A,
+            Button::Right,
+            Button::Right,
+            Button::Right,
+            Button::A,
+        ];
+        for &button in moves.chain(swap.iter()) {
+            frame_with(&mut state, button);
+        }
+        assert!(!state.animations.is_empty());
+
+        let saved = state.save();
+        let mut loaded = GameState::load(&saved).unwrap();
+        assert_eq!(loaded.save(), saved);
+
+        for _ in 0..200 {
+            frame_with(&mut state, Button::Ty::empty());
+            frame_with(&mut loaded, Button::Ty::empty());
+        }
+        frame_with(&mut state, Button::B);
+        frame_with(&mut loaded, Button::B);
+        assert_eq!(loaded.save(), state.save());
+        assert_eq!(loaded.rng.gen::<u64>(), state.rng.gen::<u64>());
+    }
+
+    #[test]
+    fn saves_from_other_versions_are_turned_away() {
+        let saved = GameState::new([3; 16], RuleSet::default()).save();
+        let other_version = saved.replacen("save 1", "save 2", 1);
+        assert_eq!(
+            GameState::load(&other_version).err().map(|e| e.kind),
+            Some(SaveParseErrorKind::UnknownVersion(2))
+        );
+
+        let cut_short: String = saved.lines().take(30).collect::<Vec<_>>().join("\n");
+        assert!(GameState::load(&cut_short).is_err());
+    }
+
+    #[test]
+    fn grids_that_do_not_fit_the_screen_or_each_other_are_turned_away() {
+        let state = GameState::new([3; 16], RuleSet::default());
+        let saved = state.save();
+        let rows = state.grid.height();
+        let grid = format!("grid {}\n{}", rows, serialize_level(&state.grid));
+        let start = format!(
+            "start {} {}\n{}",
+            state.start_cursor,
+            rows,
+            serialize_level(&state.start_grid)
+        );
+        let load_with = |from: &str, to: String| {
+            assert!(saved.contains(from));
+            GameState::load(&saved.replacen(from, &to, 1))
+                .err()
+                .map(|e| e.kind)
+        };
+
+        let too_wide = Grid::new(42, 3);
+        assert_eq!(
+            load_with(&grid, format!("grid 3\n{}", serialize_level(&too_wide))),
+            Some(SaveParseErrorKind::TooBigForScreen(42, 3))
+        );
+
+        let smaller = Grid::new(12, 9);
+        assert_eq!(
+            load_with(&start, format!("start 0 9\n{}", serialize_level(&smaller))),
+            Some(SaveParseErrorKind::DifferentSize(12, 9))
+        );
+    }
+
+    #[test]
+    fn animations_off_the_grid_are_reported_where_they_were_written() {
+        let mut state = GameState::new([3; 16], RuleSet::default());
+        let (x, y) = state.grid.i_to_xy(state.grid.len() / 2);
+        let target = state.grid.xy_to_i(x, y) + 1;
+        let animation = Animation::new(&state.grid, target - 1, target, Some(0x01), Some(1));
+        state.animations.push(animation);
+        let saved = state.save();
+
+        let written = format!("animation {} {} ", x, y);
+        let line = saved.lines().position(|l| l.starts_with(&written)).unwrap() + 1;
+        // With the grid's width added to `x`, `y * width + x` is a cell on the next row.
+        let moved = format!("animation {} {} ", x + state.grid.width(), y);
+        let error = GameState::load(&saved.replacen(&written, &moved, 1)).err();
+        assert_eq!(
+            error,
+            Some(SaveParseError {
+                line,
+                kind: SaveParseErrorKind::PositionOutOfBounds(x + state.grid.width(), y)
+            })
+        );
+    }
+}
@@ -0,0 +1,79 @@
+// Points for clearing hexes. Every cleared pair is worth `PAIR_POINTS`, times the combo, (the
+// number of swaps in a row that cleared something,) and clears that gravity causes after a swap
+// are worth more the further down the cascade they are.
+use std::cmp::max;
+
+pub const PAIR_POINTS: u32 = 10;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Score {
+    pub points: u32,
+    pub combo: u32,
+    // The number of times gravity has brought a match together since the last swap landed.
+    pub cascade: u32,
+}
+
+impl Score {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Call this for the pairs cleared by a half-hex landing at the end of a swap.
+    pub fn swap_cleared(&mut self, pairs: usize) {
+        self.add(pairs, 1);
+    }
+
+    /// Call this once both halves of a swap have landed.
+    pub fn swap_landed(&mut self, cleared_anything: bool) {
+        if cleared_anything {
+            self.combo += 1;
+        } else {
+            self.combo = 0;
+        }
+        self.cascade = 0;
+    }
+
+    /// Call this for the pairs cleared after gravity has moved things.
+    pub fn gravity_cleared(&mut self, pairs: usize) {
+        if pairs > 0 {
+            self.cascade += 1;
+            self.add(pairs, self.cascade + 1);
+        }
+    }
+
+    fn add(&mut self, pairs: usize, multiplier: u32) {
+        let points = (pairs as u32)
+            .saturating_mul(PAIR_POINTS)
+            .saturating_mul(multiplier)
+            .saturating_mul(max(self.combo, 1));
+        self.points = self.points.saturating_add(points);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combos_and_cascades_multiply_and_a_swap_without_a_clear_ends_the_combo() {
+        let mut score = Score::new();
+
+        score.swap_cleared(1);
+        score.swap_landed(true);
+        assert_eq!(score.points, PAIR_POINTS);
+
+        score.swap_cleared(1);
+        score.swap_landed(true);
+        score.gravity_cleared(1);
+        score.gravity_cleared(0);
+        score.gravity_cleared(2);
+        assert_eq!(
+            score.points,
+            PAIR_POINTS + PAIR_POINTS + PAIR_POINTS * 2 * 2 + 2 * PAIR_POINTS * 3 * 2
+        );
+        assert_eq!((score.combo, score.cascade), (2, 2));
+
+        score.swap_landed(false);
+        assert_eq!((score.combo, score.cascade), (0, 0));
+    }
+}
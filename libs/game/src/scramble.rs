@@ -0,0 +1,98 @@
+// Scramble half-hexes: when their hex matches, the half-hexes around it swap places at random.
+// Everything present within `RuleSet::scramble_radius` steps of the matched hex, counting steps
+// the way the cursor moves, gets shuffled among those same cells. Nothing is added or removed,
+// so the colour counts stay the same.
+use crate::game::{is_scramble, Grid, GridCell, HalfHexSpec};
+use crate::matching::ClearedHex;
+use rand::Rng;
+
+pub const DEFAULT_SCRAMBLE_RADIUS: usize = 3;
+
+// Shuffles around any scramble half-hexes in `cleared`, and returns every index that was part
+// of a shuffle.
+pub(crate) fn scramble_around<R: Rng>(
+    grid: &mut Grid,
+    rng: &mut R,
+    cleared: &[ClearedHex],
+    radius: usize,
+) -> Vec<usize> {
+    let mut scrambled = Vec::new();
+    for hex in cleared.iter() {
+        if !is_scramble(hex.left) && !is_scramble(hex.right) {
+            continue;
+        }
+
+        let steps = grid.steps_from(hex.index);
+        let indexes: Vec<usize> = (0..grid.len())
+            .filter(|&i| steps[i] <= radius && grid[i].is_present())
+            .collect();
+        let mut specs: Vec<HalfHexSpec> = indexes
+            .iter()
+            .filter_map(|&i| match grid[i] {
+                GridCell::Present(spec) => Some(spec),
+                _ => None,
+            })
+            .collect();
+
+        rng.shuffle(&mut specs);
+        for (&i, &spec) in indexes.iter().zip(specs.iter()) {
+            grid[i] = GridCell::Present(spec);
+        }
+        scrambled.extend(indexes);
+    }
+
+    scrambled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GRID_HEIGHT, GRID_WIDTH, SCRAMBLE};
+    use crate::matching::resolve_matches;
+    use rand::prng::XorShiftRng;
+    use rand::SeedableRng;
+
+    fn sorted_specs(grid: &Grid, indexes: &[usize]) -> Vec<HalfHexSpec> {
+        let mut specs: Vec<HalfHexSpec> = indexes
+            .iter()
+            .filter_map(|&i| match grid[i] {
+                GridCell::Present(spec) => Some(spec),
+                _ => None,
+            })
+            .collect();
+        specs.sort();
+        specs
+    }
+
+    #[test]
+    fn only_half_hexes_within_the_radius_get_shuffled() {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        let center = grid.xy_to_i(20, 30);
+        grid[center] = GridCell::Present(0x23 | SCRAMBLE);
+        grid[center + 1] = GridCell::Present(0x23);
+
+        let steps = grid.steps_from(center);
+        let mut near = Vec::new();
+        for i in 0..grid.len() {
+            if (1..=2).contains(&steps[i]) && i != center + 1 {
+                // Left and right halves get different outlines, so none of these match.
+                let outline = if i & 1 == 0 { 0x00 } else { 0x10 };
+                grid[i] = GridCell::Present(outline | (near.len() % 4) as u8);
+                near.push(i);
+            }
+        }
+        let far = grid.xy_to_i(2, 2);
+        grid[far] = GridCell::Present(0x30);
+        let before = grid.clone();
+
+        let cleared = resolve_matches(&mut grid);
+        let mut rng = XorShiftRng::from_seed([5; 16]);
+        let mut scrambled = scramble_around(&mut grid, &mut rng, &cleared, 2);
+
+        scrambled.sort();
+        assert_eq!(scrambled, near);
+        assert_eq!(sorted_specs(&grid, &near), sorted_specs(&before, &near));
+        assert!(near.iter().any(|&i| grid[i] != before[i]));
+        assert_eq!(grid[far], before[far]);
+    }
+}
@@ -0,0 +1,222 @@
+// An exhaustive search for sequences of swaps that clear every half-hex off a grid.
+//
+// Instead of playing animations out frame by frame, a swap lands both halves at once, then the
+// rules step until nothing changes any more.
+use crate::game::{get_colours, is_multi_colour, Grid, GridCell};
+use crate::rules::RuleSet;
+use rand::prng::XorShiftRng;
+use rand::SeedableRng;
+use std::collections::{HashMap, HashSet};
+
+pub type Swap = (usize, usize);
+
+// Searching only makes sense if the same swap always does the same thing, so any mechanics
+// that use randomness get the same numbers every time.
+fn solver_rng() -> XorShiftRng {
+    XorShiftRng::from_seed([0x5E; 16])
+}
+
+/// Returns `false`, and leaves `grid` alone, if either cell cannot be swapped.
+pub fn apply_swap(grid: &mut Grid, (c1, c2): Swap, rules: &RuleSet) -> bool {
+    if [c1, c2]
+        .iter()
+        .any(|&c| grid[c].is_animating() || grid[c].is_wall())
+    {
+        return false;
+    }
+
+    let mut rng = solver_rng();
+    grid.swap(c1, c2);
+    rules
+        .landed(grid, &mut rng, &[c1, c2])
+        .finish_instantly(grid);
+    rules.settle(grid, &mut rng);
+
+    true
+}
+
+pub fn settle(grid: &mut Grid, rules: &RuleSet) {
+    rules.settle(grid, &mut solver_rng());
+}
+
+/// Looks for the shortest list of swaps that empties `grid`. `None` means there is no such list
+/// with `max_depth` or fewer swaps in it. The swaps are meant to be made after `grid` has had
+/// a chance to `settle`.
+pub fn solve(grid: &Grid, max_depth: usize, rules: &RuleSet) -> Option<Vec<Swap>> {
+    let mut grid = grid.clone();
+    settle(&mut grid, rules);
+
+    if !has_even_colour_counts(&grid) {
+        return None;
+    }
+
+    let mut failures = HashMap::new();
+    let mut swaps = Vec::with_capacity(max_depth);
+    for depth in 0..=max_depth {
+        if search(&grid, depth, rules, &mut swaps, &mut failures) {
+            return Some(swaps);
+        }
+    }
+
+    None
+}
+
+// Each half-hex can only be cleared along with another one the same colour. Multi-colour ones
+// can go with more than one colour, so if there are any of those this can't rule anything out.
+pub(crate) fn has_even_colour_counts(grid: &Grid) -> bool {
+    let mut odd = HashSet::new();
+    for cell in grid.iter() {
+        if let GridCell::Present(spec) = *cell {
+            if is_multi_colour(spec) {
+                return true;
+            }
+            let colours = get_colours(spec);
+            if !odd.remove(&colours) {
+                odd.insert(colours);
+            }
+        }
+    }
+
+    odd.is_empty()
+}
+
+fn present_count(grid: &Grid) -> usize {
+    grid.iter().filter(|c| c.is_present()).count()
+}
+
+// `failures` maps grids to the largest number of swaps they are known not to be solvable in.
+fn search(
+    grid: &Grid,
+    depth: usize,
+    rules: &RuleSet,
+    swaps: &mut Vec<Swap>,
+    failures: &mut HashMap<Grid, usize>,
+) -> bool {
+    let remaining = present_count(grid);
+    if remaining == 0 {
+        return true;
+    }
+
+    if depth == 0 {
+        return false;
+    }
+
+    if let Some(&failed_depth) = failures.get(grid) {
+        if failed_depth >= depth {
+            return false;
+        }
+    }
+
+    for swap in candidate_swaps(grid, rules) {
+        let mut next = grid.clone();
+        if !apply_swap(&mut next, swap, rules) {
+            continue;
+        }
+
+        swaps.push(swap);
+        if search(&next, depth - 1, rules, swaps, failures) {
+            return true;
+        }
+        swaps.pop();
+    }
+
+    failures.insert(grid.clone(), depth);
+    false
+}
+
+// The only swaps considered are between half-hexes that are present, or between a present
+// half-hex and the empty other half of a present hex. Moving a half-hex anywhere else only
+// lets it fall back towards the others. The swaps that clear something come first, since
+// those are much more likely to be part of a solution.
+fn candidate_swaps(grid: &Grid, rules: &RuleSet) -> Vec<Swap> {
+    let present: Vec<usize> = (0..grid.len()).filter(|&i| grid[i].is_present()).collect();
+
+    let mut targets = present.clone();
+    for &i in present.iter() {
+        let partner = grid.partner_index(i);
+        if grid[partner].is_absent() {
+            targets.push(partner);
+        }
+    }
+
+    let mut swaps = Vec::with_capacity(present.len() * targets.len());
+    for &c1 in present.iter() {
+        for &c2 in targets.iter() {
+            // present pairs would otherwise show up twice.
+            if c1 < c2 || !grid[c2].is_present() {
+                swaps.push((c1, c2));
+            }
+        }
+    }
+
+    let clears_something = |&(c1, c2): &Swap| {
+        let mut next = grid.clone();
+        next.swap(c1, c2);
+        !rules
+            .landed(&mut next, &mut solver_rng(), &[c1, c2])
+            .cleared
+            .is_empty()
+    };
+
+    let (mut clearing, not_clearing): (Vec<Swap>, Vec<Swap>) =
+        swaps.into_iter().partition(clears_something);
+    clearing.extend(not_clearing);
+    clearing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GRID_HEIGHT, GRID_WIDTH};
+
+    // A couple of rows around the middle of the grid, which is where things settle.
+    fn grid_from_middle_rows(rows: &[&[u8]]) -> Grid {
+        let mut grid = Grid::new(GRID_WIDTH, GRID_HEIGHT);
+        let width = GRID_WIDTH as usize;
+        let start = (grid.len() / 2) - width + width / 2 - 2;
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &spec) in row.iter().enumerate() {
+                if spec != 0xFF {
+                    grid[start + y * width + x] = GridCell::Present(spec);
+                }
+            }
+        }
+
+        grid
+    }
+
+    #[test]
+    fn solve_finds_a_swap_that_clears_a_simple_grid() {
+        let grid = grid_from_middle_rows(&[&[0x00, 0x11, 0x11, 0x00]]);
+        let rules = RuleSet::default();
+        let solution = solve(&grid, 2, &rules).expect("should be solvable");
+
+        assert_eq!(solution.len(), 1);
+
+        let mut after = grid.clone();
+        settle(&mut after, &rules);
+        for &swap in solution.iter() {
+            apply_swap(&mut after, swap, &rules);
+        }
+        assert!(after.iter().all(|c| c.is_absent()));
+    }
+
+    #[test]
+    fn solve_gives_up_on_odd_colour_counts() {
+        let grid = grid_from_middle_rows(&[&[0x00, 0x11, 0x11, 0x00], &[0x22, 0xFF]]);
+
+        assert_eq!(solve(&grid, 4, &RuleSet::default()), None);
+    }
+
+    #[test]
+    fn solve_respects_the_depth_bound() {
+        let grid = grid_from_middle_rows(&[
+            &[0x00, 0x11, 0x22, 0x33],
+            &[0x11, 0x00, 0x33, 0x22],
+            &[0x01, 0x10, 0x10, 0x01],
+        ]);
+
+        assert_eq!(solve(&grid, 1, &RuleSet::default()), None);
+        assert!(solve(&grid, 4, &RuleSet::default()).is_some());
+    }
+}
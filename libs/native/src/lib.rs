@@ -0,0 +1,180 @@
+// A native frontend, so the game can be run with `cargo run` and stepped through in a debugger
+// without a wasm toolchain. It is used the same way as `libs/web`: `get_state_params` to make
+// the state, then `run` to drive it.
+use std::io::Cursor;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use rodio::{Decoder, OutputStream, Source};
+
+use platform_types::{Button, State, StateParams, SCREEN_HEIGHT, SCREEN_WIDTH, SFX};
+
+const TITLE: &str = "Two-Piece Hexagons";
+
+// The same layout as `PinkyWeb::on_key`, minus the workarounds for Edge.
+const KEY_MAP: [(Key, Button::Ty); 10] = [
+    (Key::Enter, Button::Start),
+    (Key::RightShift, Button::Select),
+    (Key::Up, Button::Up),
+    (Key::Left, Button::Left),
+    (Key::Right, Button::Right),
+    (Key::Down, Button::Down),
+    (Key::Z, Button::A),
+    (Key::X, Button::B),
+    // For those using the Dvorak layout.
+    (Key::Semicolon, Button::A),
+    (Key::Q, Button::B),
+];
+
+// The same volume the web version plays sounds at.
+const VOLUME: f32 = 0.375;
+
+const WUD: [&[u8]; 4] = [
+    include_bytes!("../../../static/sounds/wud0.ogg"),
+    include_bytes!("../../../static/sounds/wud1.ogg"),
+    include_bytes!("../../../static/sounds/wud2.ogg"),
+    include_bytes!("../../../static/sounds/wud3.ogg"),
+];
+
+const MOVE_PIECE: [&[u8]; 3] = [
+    include_bytes!("../../../static/sounds/movePiece0.ogg"),
+    include_bytes!("../../../static/sounds/movePiece1.ogg"),
+    include_bytes!("../../../static/sounds/movePiece2.ogg"),
+];
+
+const SCRAMBLE: [&[u8]; 1] = [include_bytes!("../../../static/sounds/scramble0.ogg")];
+
+// `handle_sound` has to be a plain `fn`, so it finds the sound thread through this.
+static SOUND_SENDER: OnceLock<Mutex<Sender<SFX>>> = OnceLock::new();
+
+fn handle_sound(request: SFX) {
+    if let Some(sender) = SOUND_SENDER.get() {
+        // If the sound thread is gone, there's nobody to tell, so the game just goes quiet.
+        let _ = sender.lock().map(|sender| sender.send(request));
+    }
+}
+
+// The output stream can't be moved between threads, so it lives on its own one, which plays
+// whatever `handle_sound` sends it.
+fn start_sound_thread() {
+    let (sender, receiver) = channel();
+    if SOUND_SENDER.set(Mutex::new(sender)).is_err() {
+        return;
+    }
+
+    thread::spawn(move || {
+        let (_stream, handle) = match OutputStream::try_default() {
+            Ok(output) => output,
+            Err(error) => {
+                error_logger(&format!("No sound: {}", error));
+                return;
+            }
+        };
+
+        // The web version picks one of the recordings at random. Going round them in turn
+        // sounds just as varied.
+        let mut count: usize = 0;
+        for request in receiver {
+            count = count.wrapping_add(1);
+            let bytes = match request {
+                SFX::Wud => WUD[count % WUD.len()],
+                SFX::MovePiece => MOVE_PIECE[count % MOVE_PIECE.len()],
+                SFX::Scramble => SCRAMBLE[count % SCRAMBLE.len()],
+            };
+
+            let played = Decoder::new(Cursor::new(bytes))
+                .map(|source| handle.play_raw(source.amplify(VOLUME).convert_samples()));
+            match played {
+                Ok(Ok(())) => {}
+                Ok(Err(error)) => error_logger(&format!("Could not play a sound: {}", error)),
+                Err(error) => error_logger(&format!("Could not decode a sound: {}", error)),
+            }
+        }
+    });
+}
+
+// The frame buffer has red in the lowest byte, but `minifb` wants it in the third.
+fn to_window_colour(colour: u32) -> u32 {
+    (colour & 0xFF) << 16 | colour & 0xFF_00 | (colour >> 16) & 0xFF
+}
+
+fn logger(s: &str) {
+    println!("{}", s);
+}
+
+fn error_logger(s: &str) {
+    eprintln!("{}", s);
+}
+
+pub fn run<S: State>(mut state: S) {
+    let options = WindowOptions {
+        scale: Scale::X2,
+        ..WindowOptions::default()
+    };
+    let mut window = match Window::new(TITLE, SCREEN_WIDTH, SCREEN_HEIGHT, options) {
+        Ok(window) => window,
+        Err(error) => {
+            error_logger(&format!("Could not open a window: {}", error));
+            std::process::exit(1);
+        }
+    };
+    window.set_target_fps(60);
+
+    start_sound_thread();
+
+    let mut buffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        for &(key, button) in KEY_MAP.iter() {
+            // Browsers send `keydown` again while a key is held, and `State::press` passes that
+            // along, so held keys repeat here too.
+            if window.is_key_pressed(key, KeyRepeat::Yes) {
+                state.press(button);
+            }
+            if window.is_key_released(key) {
+                state.release(button);
+            }
+        }
+
+        state.frame(handle_sound);
+
+        for (pixel, &colour) in buffer.iter_mut().zip(state.get_frame_buffer()) {
+            *pixel = to_window_colour(colour);
+        }
+        if let Err(error) = window.update_with_buffer(&buffer, SCREEN_WIDTH, SCREEN_HEIGHT) {
+            error_logger(&format!("Could not draw a frame: {}", error));
+            return;
+        }
+    }
+}
+
+pub fn get_state_params() -> StateParams {
+    let seed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos())
+        .unwrap_or(0)
+        .to_le_bytes();
+
+    (seed, Some(logger), Some(error_logger))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_colours_swap_red_and_blue_and_drop_alpha() {
+        // Blue, from the palette.
+        assert_eq!(to_window_colour(0xFFE15233), 0x003352E1);
+    }
+
+    #[test]
+    fn every_sound_decodes() {
+        for &bytes in WUD.iter().chain(MOVE_PIECE.iter()).chain(SCRAMBLE.iter()) {
+            let source = Decoder::new(Cursor::new(bytes)).unwrap();
+            assert!(source.count() > 0);
+        }
+    }
+}
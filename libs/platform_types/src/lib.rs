@@ -26,7 +26,7 @@ pub const SCREEN_WIDTH: usize = w!();
 pub const SCREEN_HEIGHT: usize = h!();
 pub const SCREEN_LENGTH: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
 
-#[derive(Clone, Copy, Default, Debug)]
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
 pub struct Input {
     pub gamepad: Button::Ty,
     pub previous_gamepad: Button::Ty,
@@ -54,6 +54,7 @@ impl Input {
 pub enum SFX {
     Wud,
     MovePiece,
+    Scramble,
 }
 
 impl SFX {
@@ -61,6 +62,7 @@ impl SFX {
         match *self {
             SFX::Wud => "wud",
             SFX::MovePiece => "movePiece",
+            SFX::Scramble => "scramble",
         }
     }
 }
@@ -115,4 +117,11 @@ pub trait State {
     fn release(&mut self, button: Button::Ty);
 
     fn get_frame_buffer(&self) -> &[u32];
+
+    /// Everything needed to pick up exactly where things are now, as text that starts with a
+    /// version number.
+    fn save(&self) -> String;
+
+    /// Goes back to what `save` returned. On an error, nothing changes.
+    fn load(&mut self, saved: &str) -> Result<(), Box<dyn std::error::Error>>;
 }
@@ -437,6 +437,26 @@ fn handle_error<E: Into<Box<dyn Error>>>(error: E) {
     show("error");
 }
 
+const SAVE_KEY: &'static str = "two_piece_hexagons_save";
+
+// Picks up where the last session left off, and saves this one when the page goes away, so
+// reloading the tab doesn't lose anything.
+fn support_saving<S: State + 'static>(pinky: Rc<RefCell<PinkyWeb<S>>>) {
+    if let Some(saved) = web::window().local_storage().get(SAVE_KEY) {
+        if let Err(error) = pinky.borrow_mut().state.load(&saved) {
+            console!(error, format!("Could not load the saved game: {}", error));
+        }
+    }
+
+    let save = move || pinky.borrow().state.save();
+    js! {
+        var save = @{save};
+        window.addEventListener("beforeunload", function() {
+            localStorage.setItem(@{SAVE_KEY}, save());
+        });
+    };
+}
+
 pub fn run<S: State + 'static>(state: S) {
     stdweb::initialize();
 
@@ -445,6 +465,7 @@ pub fn run<S: State + 'static>(state: S) {
     let pinky = Rc::new(RefCell::new(PinkyWeb::new(&canvas, state)));
 
     support_input(pinky.clone());
+    support_saving(pinky.clone());
 
     hide("loading");
     hide("error");
@@ -1,9 +1,12 @@
-use web;
+// `cargo web` builds for the browser, and plain `cargo run` opens a window on the desktop.
+#[cfg(any(target_arch = "wasm32", target_arch = "asmjs"))]
+use web as platform;
 
-use game;
+#[cfg(not(any(target_arch = "wasm32", target_arch = "asmjs")))]
+use native as platform;
 
 fn main() {
-    let params = web::get_state_params();
+    let params = platform::get_state_params();
     let state = game::EntireState::new(params);
-    web::run(state);
+    platform::run(state);
 }
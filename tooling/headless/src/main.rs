@@ -0,0 +1,234 @@
+// Runs the game without a browser, following a script of button presses, and writes the
+// frames the script asks for out as PPM files. Handy for screenshots on CI machines.
+//
+// Usage: headless SCRIPT [OUTPUT_DIRECTORY]
+//
+// A script is one command per line. Blank lines, and anything after a `#`, are skipped.
+//
+//    seed 000102030405060708090a0b0c0d0e0f  start over with this seed
+//    press A Left                           hold these buttons down
+//    release A                              let go of these buttons
+//    tap Start                              press, run one frame, then release
+//    wait 30                                run this many frames
+//    dump start.ppm                         write the current frame to this file
+//
+// Without a `seed` line, every run uses `DEFAULT_SEED`, so the same script gives the same
+// pictures each time.
+use game::{parse_seed, to_ppm, EntireState};
+use platform_types::{Button, State, SFX};
+
+use std::fmt;
+use std::fs;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_SEED: [u8; 16] = [
+    0x2a, 0x1c, 0x5e, 0x93, 0x07, 0xd4, 0x68, 0xb1, 0x3f, 0x80, 0xc2, 0x19, 0x7a, 0xe5, 0x46, 0x0b,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Command {
+    Seed([u8; 16]),
+    Press(Button::Ty),
+    Release(Button::Ty),
+    Tap(Button::Ty),
+    Wait(usize),
+    Dump(PathBuf),
+}
+
+#[derive(Debug)]
+struct ScriptError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+fn parse_button(word: &str) -> Option<Button::Ty> {
+    Some(match word {
+        "A" => Button::A,
+        "B" => Button::B,
+        "Select" => Button::Select,
+        "Start" => Button::Start,
+        "Up" => Button::Up,
+        "Down" => Button::Down,
+        "Left" => Button::Left,
+        "Right" => Button::Right,
+        _ => return None,
+    })
+}
+
+fn parse_script(text: &str) -> Result<Vec<Command>, ScriptError> {
+    let mut commands = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let error = |message: String| ScriptError {
+            line: index + 1,
+            message,
+        };
+
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => continue,
+        };
+        let rest: Vec<&str> = words.collect();
+
+        let buttons = || {
+            if rest.is_empty() {
+                return Err(error(format!("`{}` needs at least one button", name)));
+            }
+            rest.iter().try_fold(Button::Ty::empty(), |acc, word| {
+                parse_button(word)
+                    .map(|button| acc | button)
+                    .ok_or_else(|| error(format!("unknown button `{}`", word)))
+            })
+        };
+        let single = || match rest.as_slice() {
+            [word] => Ok(*word),
+            _ => Err(error(format!("`{}` takes exactly one argument", name))),
+        };
+
+        commands.push(match name {
+            "seed" => {
+                let word = single()?;
+                Command::Seed(
+                    parse_seed(word)
+                        .ok_or_else(|| error(format!("`{}` is not 32 hex digits", word)))?,
+                )
+            }
+            "press" => Command::Press(buttons()?),
+            "release" => Command::Release(buttons()?),
+            "tap" => Command::Tap(buttons()?),
+            "wait" => {
+                let word = single()?;
+                Command::Wait(
+                    word.parse()
+                        .map_err(|_| error(format!("`{}` is not a frame count", word)))?,
+                )
+            }
+            "dump" => Command::Dump(PathBuf::from(single()?)),
+            _ => return Err(error(format!("unknown command `{}`", name))),
+        });
+    }
+
+    Ok(commands)
+}
+
+fn new_state(seed: [u8; 16]) -> EntireState {
+    EntireState::new((seed, None, None))
+}
+
+fn no_sound(_: SFX) {}
+
+// Runs `commands` from the start, handing every frame a `dump` asks for to `dump`.
+fn run<F>(commands: &[Command], mut dump: F) -> std::io::Result<()>
+where
+    F: FnMut(&Path, &[u32]) -> std::io::Result<()>,
+{
+    let mut state = new_state(DEFAULT_SEED);
+
+    for command in commands {
+        match *command {
+            Command::Seed(seed) => state = new_state(seed),
+            Command::Press(buttons) => state.press(buttons),
+            Command::Release(buttons) => state.release(buttons),
+            Command::Tap(buttons) => {
+                state.press(buttons);
+                state.frame(no_sound);
+                state.release(buttons);
+            }
+            Command::Wait(frames) => {
+                for _ in 0..frames {
+                    state.frame(no_sound);
+                }
+            }
+            Command::Dump(ref path) => dump(path, state.get_frame_buffer())?,
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let script_path = args
+        .next()
+        .ok_or("usage: headless SCRIPT [OUTPUT_DIRECTORY]")?;
+    let output_directory = PathBuf::from(args.next().unwrap_or_else(|| ".".to_owned()));
+
+    let commands = parse_script(&fs::read_to_string(&script_path)?)?;
+    fs::create_dir_all(&output_directory)?;
+    run(&commands, |path, frame_buffer| {
+        let path = output_directory.join(path);
+        fs::File::create(&path)?.write_all(&to_ppm(frame_buffer))?;
+        println!("wrote {}", path.display());
+        Ok(())
+    })?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use platform_types::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+    #[test]
+    fn scripts_parse_and_report_the_line_that_is_wrong() {
+        let commands = parse_script(
+            "# a comment\n\
+             press A Left\n\
+             \n\
+             wait 3 # trailing comment\n\
+             release A\n\
+             tap Start\n\
+             dump out.ppm\n",
+        )
+        .unwrap();
+        assert_eq!(
+            commands,
+            vec![
+                Command::Press(Button::A | Button::Left),
+                Command::Wait(3),
+                Command::Release(Button::A),
+                Command::Tap(Button::Start),
+                Command::Dump(PathBuf::from("out.ppm")),
+            ]
+        );
+
+        assert_eq!(parse_script("wait 1\npress C").unwrap_err().line, 2);
+        assert_eq!(parse_script("seed 00").unwrap_err().line, 1);
+        assert_eq!(parse_script("jump").unwrap_err().line, 1);
+    }
+
+    #[test]
+    fn the_same_seed_and_presses_give_the_same_pictures() {
+        let commands = parse_script("wait 2\ndump a\ntap Right\nwait 10\ndump b").unwrap();
+        let render = || {
+            let mut dumps = Vec::new();
+            run(&commands, |path, frame_buffer| {
+                dumps.push((path.to_owned(), to_ppm(frame_buffer)));
+                Ok(())
+            })
+            .unwrap();
+            dumps
+        };
+
+        let dumps = render();
+        assert_eq!(dumps.len(), 2);
+        assert_ne!(dumps[0].1, dumps[1].1);
+        for (_, ppm) in &dumps {
+            assert!(ppm.starts_with(b"P6\n256 256\n255\n"));
+            assert_eq!(ppm.len(), 15 + SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        }
+        assert_eq!(dumps, render());
+    }
+}